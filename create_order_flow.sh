@@ -6,9 +6,12 @@ near view limit_orders.v1.nearlend.testnet balance_of '{"account_id": "nearlend.
 
 wait
 
-near call limit_orders.v1.nearlend.testnet create_order '{"order_type": "Buy", "amount": "10000000", "sell_token": "usdt.qa.v1.nearlend.testnet", "buy_token": "wnear.qa.v1.nearlend.testnet", "leverage": "1"}' --accountId nearlend.testnet --gas 300000000000000
+near call limit_orders.v1.nearlend.testnet create_order '{"order_type": "Buy", "amount": "10000000", "sell_token": "usdt.qa.v1.nearlend.testnet", "buy_token": "wnear.qa.v1.nearlend.testnet", "leverage": "1"}' --accountId nearlend.testnet --amount 0.01 --gas 300000000000000
 
 # make sure lpt id is valid
 near view limit_orders.v1.nearlend.testnet view_orders '{    "account_id":"nearlend.testnet ",
                                                           "buy_token":"wnear.qa.v1.nearlend.testnet",
                                                           "sell_token":"usdt.qa.v1.nearlend.testnet"}'
+
+# alternatively, deposit & create order within a single transfer, unused amount is returned
+near call usdt.qa.v1.nearlend.testnet ft_transfer_call '{"receiver_id": "limit_orders.v1.nearlend.testnet", "amount": "10000000", "msg": "{\"CreateOrder\": {\"order_type\": \"Buy\", \"sell_token\": \"usdt.qa.v1.nearlend.testnet\", \"buy_token\": \"wnear.qa.v1.nearlend.testnet\", \"leverage\": \"1\", \"amount\": \"9000000\", \"executor_bounty\": \"1000000\"}}"}' --accountId nearlend.testnet --depositYocto 1 --gas 300000000000000
@@ -16,10 +16,15 @@ CONTRACT_ADDRESS=leverage_omomo.testnet
 near deploy ${CONTRACT_ADDRESS} \
     --wasmFile ./res/limit_orders.wasm
 #   --wasmFile ./res/limit_orders.wasm \
-#   --initFunction 'new_with_config' \
+#   --initFunction 'new_with_accounts' \
 #   --initArgs '{
 #         "owner_id":"'${CONTRACT_ADDRESS}'",
-#         "oracle_account_id":"limit_orders_oracle.v1.nearlend.testnet"
+#         "oracle_account_id":"limit_orders_oracle.v1.nearlend.testnet",
+#         "ref_finance_account":"dcl.ref-dev.testnet",
+#         "token_markets":[
+#             ["wnear.qa.v1.nearlend.testnet", "wnear_market.qa.v1.nearlend.testnet"],
+#             ["usdt.qa.v1.nearlend.testnet", "usdt_market.qa.v1.nearlend.testnet"]
+#         ]
 #     }'
 
 # register limit orders on tokens
@@ -27,29 +32,46 @@ near call wnear.qa.v1.nearlend.testnet storage_deposit '{"account_id": "'${CONTR
 near call usdt.qa.v1.nearlend.testnet storage_deposit '{"account_id": "'${CONTRACT_ADDRESS}'"}' --accountId ${CONTRACT_ADDRESS} --amount 0.25 &
 wait
 
-# add supported pairs
-near call ${CONTRACT_ADDRESS} add_pair '{
-        "pair_data": {
-            "sell_ticker_id": "USDt",
-            "sell_token": "usdt.qa.v1.nearlend.testnet",
-            "sell_token_market": "usdt_market.qa.v1.nearlend.testnet",
-            "buy_ticker_id": "near",
-            "buy_token": "wnear.qa.v1.nearlend.testnet",
-            "pool_id": "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000"
+# set markets of the tokens, which are verified on pair registration
+near call ${CONTRACT_ADDRESS} add_token_market '{"token_id": "wnear.qa.v1.nearlend.testnet", "market_id": "wnear_market.qa.v1.nearlend.testnet"}' --account_id ${CONTRACT_ADDRESS} &
+near call ${CONTRACT_ADDRESS} add_token_market '{"token_id": "usdt.qa.v1.nearlend.testnet", "market_id": "usdt_market.qa.v1.nearlend.testnet"}' --account_id ${CONTRACT_ADDRESS} &
+wait
+
+# propose supported pairs, which could be applied after the timelock delay
+near call ${CONTRACT_ADDRESS} propose_change '{
+        "change": {
+            "AddPair": {
+                "pair_data": {
+                    "sell_ticker_id": "USDt",
+                    "sell_token": "usdt.qa.v1.nearlend.testnet",
+                    "sell_token_market": "usdt_market.qa.v1.nearlend.testnet",
+                    "buy_ticker_id": "near",
+                    "buy_token": "wnear.qa.v1.nearlend.testnet",
+                    "pool_id": "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000"
+                }
+            }
         }
     }' --accountId ${CONTRACT_ADDRESS} &
 
-near call ${CONTRACT_ADDRESS} add_pair '{
-        "pair_data": {
-            "sell_ticker_id": "near",
-            "sell_token": "wnear.qa.v1.nearlend.testnet",
-            "sell_token_market": "wnear_market.qa.v1.nearlend.testnet",
-            "buy_ticker_id": "USDt",
-            "buy_token": "usdt.qa.v1.nearlend.testnet",
-            "pool_id": "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000"
+near call ${CONTRACT_ADDRESS} propose_change '{
+        "change": {
+            "AddPair": {
+                "pair_data": {
+                    "sell_ticker_id": "near",
+                    "sell_token": "wnear.qa.v1.nearlend.testnet",
+                    "sell_token_market": "wnear_market.qa.v1.nearlend.testnet",
+                    "buy_ticker_id": "USDt",
+                    "buy_token": "usdt.qa.v1.nearlend.testnet",
+                    "pool_id": "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000"
+                }
+            }
         }
     }' --accountId ${CONTRACT_ADDRESS} &
 
+# near view ${CONTRACT_ADDRESS} view_proposals '{}'
+# near call ${CONTRACT_ADDRESS} apply_proposal '{"proposal_id": "0"}' --accountId ${CONTRACT_ADDRESS}
+# near call ${CONTRACT_ADDRESS} apply_proposal '{"proposal_id": "1"}' --accountId ${CONTRACT_ADDRESS}
+
 # near view ${CONTRACT_ADDRESS} view_supported_pairs '{}'
 
 # add mock prices
@@ -108,9 +130,6 @@ near view ${CONTRACT_ADDRESS} view_price '{"token_id":"wnear.qa.v1.nearlend.test
 # setup pool
 near call dcl.ref-dev.testnet storage_deposit '{"account_id": "'${CONTRACT_ADDRESS}'"}' --accountId nearlend.testnet --amount 1 &
 
-near call ${CONTRACT_ADDRESS} add_token_market '{"token_id": "wnear.qa.v1.nearlend.testnet", "market_id": "wnear_market.qa.v1.nearlend.testnet"}' --account_id ${CONTRACT_ADDRESS} &
-near call ${CONTRACT_ADDRESS} add_token_market '{"token_id": "usdt.qa.v1.nearlend.testnet", "market_id": "usdt_market.qa.v1.nearlend.testnet"}' --account_id ${CONTRACT_ADDRESS} &
-
 near call usdt_market.qa.v1.nearlend.testnet set_eligible_to_borrow_uncollateralized_account '{ "account": "'${CONTRACT_ADDRESS}'" }' --accountId shared_admin.testnet
 near view usdt_market.qa.v1.nearlend.testnet get_eligible_to_borrow_uncollateralized_account '{ "account": "'${CONTRACT_ADDRESS}'" }'
 
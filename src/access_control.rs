@@ -0,0 +1,155 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Grants the role to the account. Admin role could be granted by the owner only.
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_role_manager(role);
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if !roles.contains(&role) {
+            roles.push(role);
+            self.roles.insert(&account_id, &roles);
+        }
+    }
+
+    /// Revokes the role from the account. Admin role could be revoked by the owner only.
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_role_manager(role);
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        roles.retain(|account_role| *account_role != role);
+        if roles.is_empty() {
+            self.roles.remove(&account_id);
+        } else {
+            self.roles.insert(&account_id, &roles);
+        }
+    }
+
+    /// First step of the ownership transfer, which has to be accepted by the new owner.
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        self.assert_owner();
+        self.pending_owner = Some(new_owner);
+    }
+
+    /// Second step of the ownership transfer made by the proposed owner.
+    pub fn accept_ownership(&mut self) {
+        let account_id = env::predecessor_account_id();
+        require!(
+            self.pending_owner.as_ref() == Some(&account_id),
+            "Only proposed owner can accept the ownership"
+        );
+
+        self.config.owner_id = account_id;
+        self.pending_owner = None;
+    }
+
+    pub fn view_owner(&self) -> AccountId {
+        self.config.owner_id.clone()
+    }
+
+    pub fn view_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+
+    pub fn view_roles(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+
+    pub fn view_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.roles
+            .iter()
+            .filter(|(_, roles)| roles.contains(&role))
+            .map(|(account_id, _)| account_id)
+            .collect()
+    }
+}
+
+impl Contract {
+    pub fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.config.owner_id,
+            "This method can be called by the owner only"
+        );
+    }
+
+    /// Checks whether the account is the owner, admin or has the given role.
+    pub fn has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        if *account_id == self.config.owner_id {
+            return true;
+        }
+
+        self.roles
+            .get(account_id)
+            .map(|roles| roles.contains(&role) || roles.contains(&Role::Admin))
+            .unwrap_or(false)
+    }
+
+    pub fn assert_role(&self, role: Role) {
+        require!(
+            self.has_role(&env::predecessor_account_id(), role),
+            format!("This method requires {:?} role", role)
+        );
+    }
+
+    fn assert_role_manager(&self, role: Role) {
+        match role {
+            Role::Admin => self.assert_owner(),
+            _ => self.assert_role(Role::Admin),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::test_env::{alice, bob};
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    fn set_predecessor(account_id: AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(account_id)
+            .build());
+    }
+
+    #[test]
+    fn test_admin_manages_roles_and_ownership_is_transferred_in_two_steps() {
+        let owner: AccountId = "owner_id.testnet".parse().unwrap();
+        let mut contract =
+            Contract::new_with_config(owner.clone(), "oracle_account_id.testnet".parse().unwrap());
+
+        set_predecessor(owner.clone());
+        contract.grant_role(alice(), Role::Admin);
+
+        set_predecessor(alice());
+        contract.grant_role(bob(), Role::PriceFeeder);
+        assert!(contract.has_role(&bob(), Role::PriceFeeder));
+        assert!(!contract.has_role(&bob(), Role::RiskManager));
+        assert_eq!(contract.view_role_members(Role::PriceFeeder), vec![bob()]);
+
+        contract.revoke_role(bob(), Role::PriceFeeder);
+        assert!(contract.view_roles(bob()).is_empty());
+
+        set_predecessor(owner);
+        contract.propose_owner(bob());
+        set_predecessor(bob());
+        contract.accept_ownership();
+        assert_eq!(contract.view_owner(), bob());
+        assert_eq!(contract.view_pending_owner(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "This method can be called by the owner only")]
+    fn test_admin_cant_grant_admin_role() {
+        let owner: AccountId = "owner_id.testnet".parse().unwrap();
+        let mut contract =
+            Contract::new_with_config(owner.clone(), "oracle_account_id.testnet".parse().unwrap());
+
+        set_predecessor(owner);
+        contract.grant_role(alice(), Role::Admin);
+
+        set_predecessor(alice());
+        contract.grant_role(bob(), Role::Admin);
+    }
+}
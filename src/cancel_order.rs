@@ -1,11 +1,11 @@
-use crate::big_decimal::{BigDecimal, WRatio};
+use crate::big_decimal::{BigDecimal, WBalance};
 use crate::ref_finance::ext_ref_finance;
 use crate::ref_finance::{Action, Swap};
 use crate::utils::NO_DEPOSIT;
 use crate::utils::{ext_market, ext_token};
 use crate::*;
-use near_sdk::env::{block_height, current_account_id, signer_account_id};
-use near_sdk::{ext_contract, is_promise_success, log, Gas, PromiseResult, ONE_YOCTO};
+use near_sdk::env::{block_height, current_account_id};
+use near_sdk::{ext_contract, log, Gas, PromiseResult, ONE_YOCTO};
 
 #[ext_contract(ext_self)]
 trait ContractCallbackInterface {
@@ -13,51 +13,64 @@ trait ContractCallbackInterface {
         &self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
-        order_action: OrderAction,
+        liquidator: AccountId,
+        market_data: MarketData,
+        unclaimed_fee_x: U128,
+        unclaimed_fee_y: U128,
     );
-    fn order_cancel_swap_callback(
+    fn liquidate_market_data_callback(&self, order_id: U128, order: Order, liquidator: AccountId);
+    fn liquidate_liquidity_callback(
         &self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
-        order_action: OrderAction,
+        liquidator: AccountId,
+        market_data: MarketData,
     );
-    fn market_data_callback(
+    fn swap_result_callback(&self, order_id: U128);
+    fn get_pool_callback(
         &self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
         order_action: OrderAction,
+        account_id: AccountId,
+        min_out: U128,
     );
-    fn get_pool_callback(
+    fn get_liquidity_callback(
         &self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
         order_action: OrderAction,
+        account_id: AccountId,
+        pool_info: PoolInfo,
+        market_data: MarketData,
     );
-    fn get_liquidity_callback(
+    #[allow(clippy::too_many_arguments)]
+    fn pending_order_cancel_callback(
         &self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
         order_action: OrderAction,
-        pool_info: PoolInfo,
+        account_id: AccountId,
+        market_data: MarketData,
+        unclaimed_fee_x: U128,
+        unclaimed_fee_y: U128,
     );
-    fn repay_callback(&self) -> PromiseOrValue<U128>;
 }
 
 #[near_bindgen]
 impl Contract {
-    pub fn cancel_order(&mut self, order_id: U128, swap_fee: U128, price_impact: U128) {
-        let orders = self.orders.get(&signer_account_id()).unwrap_or_else(|| {
-            panic!("Orders for account: {} not found", signer_account_id());
+    /// Cancels the order of the predecessor account.
+    ///
+    /// Pending order liquidity is removed from ref finance & returned to the user without swap,
+    /// while executed position is swapped
+    /// back into the sell token with at least `min_out` received.
+    /// User balance is credited with the actual output.
+    ///
+    /// Order is marked as `Closing` until the flow is finished, so it can't be processed concurrently.
+    pub fn cancel_order(&mut self, order_id: U128, min_out: U128) {
+        let account_id = env::predecessor_account_id();
+        let orders = self.orders.get(&account_id).unwrap_or_else(|| {
+            panic!("Orders for account: {} not found", account_id);
         });
 
         let order = orders
@@ -66,22 +79,16 @@ impl Contract {
                 panic!("Order with id: {} not found", order_id.0);
             })
             .clone();
-        ext_ref_finance::ext(self.ref_finance_account.clone())
-            .with_unused_gas_weight(1)
-            .with_attached_deposit(NO_DEPOSIT)
-            .get_pool(self.view_pair(&order.sell_token, &order.buy_token).pool_id)
-            .then(
-                ext_self::ext(current_account_id())
-                    .with_unused_gas_weight(29)
-                    .with_attached_deposit(NO_DEPOSIT)
-                    .get_pool_callback(
-                        order_id,
-                        order,
-                        swap_fee,
-                        price_impact,
-                        OrderAction::Cancel,
-                    ),
-            );
+
+        require!(
+            self.is_order_open(&order),
+            "Order has to be Pending or Executed to be canceled"
+        );
+        self.assert_not_paused(
+            PauseAction::CancelOrder,
+            Some((&order.sell_token, &order.buy_token)),
+        );
+        self.close_order(account_id, order_id, order, OrderAction::Cancel, min_out);
     }
 
     #[private]
@@ -89,30 +96,45 @@ impl Contract {
         &mut self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
         order_action: OrderAction,
+        account_id: AccountId,
+        min_out: U128,
     ) {
         require!(
-            is_promise_success(),
-            "Some problem with pool on ref finance"
+            env::promise_results_count() == 2,
+            "Contract expected 2 results on the callback"
         );
+
         let pool_info = match env::promise_result(0) {
-            PromiseResult::NotReady => unreachable!(),
             PromiseResult::Successful(val) => {
-                if let Ok(pool) = near_sdk::serde_json::from_slice::<PoolInfo>(&val) {
-                    pool
-                } else {
-                    panic!("Some problem with pool parsing.")
-                }
+                near_sdk::serde_json::from_slice::<PoolInfo>(&val).ok()
             }
-            PromiseResult::Failed => panic!("Ref finance not found pool"),
+            _ => None,
         };
 
-        require!(
-            pool_info.state == PoolState::Running,
-            "Some problem with pool, please contact with ref finance to support."
-        );
+        let pool_info = match pool_info {
+            Some(pool_info) if pool_info.state == PoolState::Running => pool_info,
+            _ => {
+                self.revert_order(order_id, order, "Some problem with pool on ref finance");
+                return;
+            }
+        };
+
+        // market data is required to settle the debt, so it's checked before liquidity is touched
+        let market_data = match env::promise_result(1) {
+            PromiseResult::Successful(val) => {
+                near_sdk::serde_json::from_slice::<MarketData>(&val).ok()
+            }
+            _ => None,
+        };
+
+        let market_data = match market_data {
+            Some(market_data) => market_data,
+            None => {
+                self.revert_order(order_id, order, "Failed to get market data");
+                return;
+            }
+        };
 
         if order.status == OrderStatus::Pending {
             ext_ref_finance::ext(self.ref_finance_account.clone())
@@ -126,14 +148,21 @@ impl Contract {
                         .get_liquidity_callback(
                             order_id,
                             order,
-                            swap_fee,
-                            price_impact,
                             order_action,
+                            account_id,
                             pool_info,
+                            market_data,
                         ),
                 );
         } else {
-            self.swap(order_id, order, swap_fee, price_impact, order_action);
+            self.swap(
+                order_id,
+                order,
+                order_action,
+                account_id,
+                market_data,
+                min_out,
+            );
         }
     }
 
@@ -142,35 +171,41 @@ impl Contract {
         &mut self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
         order_action: OrderAction,
+        account_id: AccountId,
         pool_info: PoolInfo,
+        market_data: MarketData,
     ) {
-        require!(
-            is_promise_success(),
-            "Some problem with liquidity on ref finance"
-        );
-        let liquidity: Liquidity = match env::promise_result(0) {
-            PromiseResult::NotReady => unreachable!(),
+        let liquidity = match env::promise_result(0) {
             PromiseResult::Successful(val) => {
-                if let Ok(pool) = near_sdk::serde_json::from_slice::<Liquidity>(&val) {
-                    pool
-                } else {
-                    panic!("Some problem with liquidity parsing.")
-                }
+                near_sdk::serde_json::from_slice::<Liquidity>(&val).ok()
+            }
+            _ => None,
+        };
+
+        let liquidity = match liquidity {
+            Some(liquidity) => liquidity,
+            None => {
+                self.revert_order(
+                    order_id,
+                    order,
+                    "Some problem with liquidity on ref finance",
+                );
+                return;
             }
-            PromiseResult::Failed => panic!("Ref finance not found liquidity"),
         };
 
+        // partially filled order liquidity is returned in both tokens
         let remove_liquidity_amount = liquidity.amount.0;
-        let min_amount_x = liquidity.amount.0 - 1000;
+        let min_amount_x = 0;
         let min_amount_y = 0;
 
-        require!(
-            pool_info.total_x.0 > remove_liquidity_amount,
-            "Pool not have enough liquidity"
-        );
+        let (pool_sell_amount, _) =
+            self.map_pool_amounts(&order, pool_info.total_x.0, pool_info.total_y.0);
+        if pool_sell_amount <= remove_liquidity_amount {
+            self.revert_order(order_id, order, "Pool not have enough liquidity");
+            return;
+        }
 
         ext_ref_finance::ext(self.ref_finance_account.clone())
             .with_unused_gas_weight(50)
@@ -185,48 +220,249 @@ impl Contract {
                 ext_self::ext(current_account_id())
                     .with_unused_gas_weight(50)
                     .with_attached_deposit(NO_DEPOSIT)
-                    .remove_liquidity_callback(
+                    .pending_order_cancel_callback(
                         order_id,
                         order,
-                        swap_fee,
-                        price_impact,
                         order_action,
+                        account_id,
+                        market_data,
+                        liquidity.unclaimed_fee_x,
+                        liquidity.unclaimed_fee_y,
                     ),
             );
     }
 
+    #[allow(clippy::too_many_arguments)]
+    #[private]
+    pub fn pending_order_cancel_callback(
+        &mut self,
+        order_id: U128,
+        order: Order,
+        order_action: OrderAction,
+        account_id: AccountId,
+        market_data: MarketData,
+        unclaimed_fee_x: U128,
+        unclaimed_fee_y: U128,
+    ) {
+        let mut order = order;
+        let removed_amounts = match env::promise_result(0) {
+            PromiseResult::Successful(val) => {
+                near_sdk::serde_json::from_slice::<(U128, U128)>(&val).ok()
+            }
+            _ => None,
+        };
+
+        let (sell_amount, buy_amount) = match removed_amounts {
+            Some((amount_x, amount_y)) => self.map_pool_amounts(&order, amount_x, amount_y),
+            None => {
+                self.revert_order(order_id, order, "Some problem with remove liquidity");
+                return;
+            }
+        };
+
+        self.credit_pool_fees(
+            &account_id,
+            &mut order,
+            unclaimed_fee_x.0,
+            unclaimed_fee_y.0,
+        );
+
+        self.final_pending_order_cancel(
+            order_id,
+            order,
+            order_action,
+            account_id,
+            market_data,
+            sell_amount,
+            buy_amount,
+        );
+    }
+
+    /// Closes the position of the liquidated pending order from its removed liquidity.
     #[private]
     pub fn remove_liquidity_callback(
         &mut self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
+        liquidator: AccountId,
+        market_data: MarketData,
+        unclaimed_fee_x: U128,
+        unclaimed_fee_y: U128,
+    ) {
+        let mut order = order;
+        let removed_amounts = match env::promise_result(0) {
+            PromiseResult::Successful(val) => {
+                near_sdk::serde_json::from_slice::<(U128, U128)>(&val).ok()
+            }
+            _ => None,
+        };
+
+        let (sell_amount, buy_amount) = match removed_amounts {
+            Some((amount_x, amount_y)) => self.map_pool_amounts(&order, amount_x, amount_y),
+            None => {
+                self.revert_order(order_id, order, "Some problem with remove liquidity");
+                return;
+            }
+        };
+
+        let owner_id = self.get_account_by(order_id.0).unwrap();
+        self.credit_pool_fees(&owner_id, &mut order, unclaimed_fee_x.0, unclaimed_fee_y.0);
+
+        // removed liquidity is held by the contract the same way as of executed order,
+        // so the order is reverted to `Executed` if the following swap fails
+        order.status = OrderStatus::Executed;
+        order.remaining_sell_amount = sell_amount.0;
+        order.filled_buy_amount = buy_amount.0;
+
+        if buy_amount.0 > 0 {
+            // partially filled range is returned in both tokens, so filled buy token is swapped back
+            let min_out = self.calculate_swap_floor(&order);
+            self.swap(
+                order_id,
+                order,
+                OrderAction::Liquidate,
+                liquidator,
+                market_data,
+                min_out,
+            );
+        } else {
+            self.settle_order(
+                order_id,
+                order,
+                OrderAction::Liquidate,
+                liquidator,
+                market_data,
+                U128(0),
+            );
+        }
+    }
+
+    /// Checks whether ref finance used the swapped buy token & settles the order
+    /// once the swap output is received.
+    #[private]
+    pub fn swap_result_callback(&mut self, order_id: U128) {
+        let used_amount = match env::promise_result(0) {
+            PromiseResult::Successful(val) => near_sdk::serde_json::from_slice::<U128>(&val)
+                .map(|amount| amount.0)
+                .unwrap_or(0),
+            _ => 0,
+        };
+
+        let mut pending_swap = self
+            .pending_swaps
+            .get(&(order_id.0 as u64))
+            .unwrap_or_else(|| panic!("Swap for order with id: {} not found", order_id.0));
+
+        // failed swap is refunded by the buy token, so order is left with its prior status
+        if used_amount == 0 {
+            self.pending_swaps.remove(&(order_id.0 as u64));
+            self.revert_order(order_id, pending_swap.order, "Swap on ref finance failed");
+            return;
+        }
+
+        pending_swap.is_input_used = true;
+        self.settle_swap(order_id, pending_swap);
+    }
+
+    /// Credits user with the actual swap output & the not filled part of the order
+    /// reduced by the debt, closing fee and profit share fee.
+    fn final_order_cancel(
+        &mut self,
+        order_id: U128,
+        order: Order,
         order_action: OrderAction,
+        account_id: AccountId,
+        market_data: MarketData,
+        swap_output: U128,
     ) {
-        require!(is_promise_success(), "Some problem with remove liquidity");
-        self.order_cancel_swap_callback(order_id, order, swap_fee, price_impact, order_action);
+        log!("Final order cancel attached gas: {}", env::prepaid_gas().0);
+
+        let mut order = order;
+        let total_amount = swap_output.0 + order.remaining_sell_amount;
+        let debt = self.calculate_debt(&order, &market_data);
+
+        let fees = self.get_account_fees(&account_id, &order.sell_token, &order.buy_token);
+        self.record_trade_volume(&account_id, &order.sell_token, swap_output.0);
+
+        let mut return_amount = total_amount.saturating_sub(debt);
+        let closing_fee = Self::calculate_fee(total_amount, fees.closing_fee).min(return_amount);
+        return_amount -= closing_fee;
+
+        let profit_share = if return_amount > order.amount {
+            Self::calculate_fee(return_amount - order.amount, fees.profit_share_fee)
+        } else {
+            0
+        };
+        return_amount -= profit_share;
+
+        self.collect_trading_fee(&account_id, &order.sell_token, closing_fee);
+        self.add_protocol_profit(&order.sell_token, profit_share);
+
+        if debt > 0 {
+            self.repay(&account_id, &order, debt.min(total_amount));
+        }
+
+        self.increase_balance(&account_id, &order.sell_token, return_amount);
+        self.settle_cancel_bounty(&mut order, &order_action, &account_id);
+
+        order.status = OrderStatus::Canceled;
+        self.insert_order_for_user(&account_id, order, order_id.0 as u64);
     }
 
-    pub fn swap(
-        &self,
+    /// Returns removed liquidity of the pending order to the user balances: sell token
+    /// reduced by the debt to be repaid & buy token of the partially filled range.
+    #[allow(clippy::too_many_arguments)]
+    fn final_pending_order_cancel(
+        &mut self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
         order_action: OrderAction,
+        account_id: AccountId,
+        market_data: MarketData,
+        sell_amount: U128,
+        buy_amount: U128,
     ) {
-        let buy_amount = BigDecimal::from(U128::from(order.amount))
-            * order.leverage
-            * order.sell_token_price.value
-            * self.get_price(order.buy_token.clone())
-            / order.buy_token_price.value;
+        let mut order = order;
+        let debt = self.calculate_debt(&order, &market_data).min(sell_amount.0);
+
+        if debt > 0 {
+            self.repay(&account_id, &order, debt);
+        }
 
+        self.increase_balance(&account_id, &order.sell_token, sell_amount.0 - debt);
+        if buy_amount.0 > 0 {
+            self.increase_balance(&account_id, &order.buy_token, buy_amount.0);
+        }
+        self.settle_cancel_bounty(&mut order, &order_action, &account_id);
+
+        order.status = OrderStatus::Canceled;
+        self.insert_order_for_user(&account_id, order, order_id.0 as u64);
+    }
+}
+
+impl Contract {
+    /// Swaps filled buy token amount of the order back into the sell token on ref finance.
+    ///
+    /// Swap is recorded as pending for the order & ref finance sends the output back
+    /// with the order id echoed, so the actual output is accounted per order
+    /// regardless of the other transfers. Swap fails on ref finance if output is less than `min_out`.
+    pub fn swap(
+        &mut self,
+        order_id: U128,
+        order: Order,
+        order_action: OrderAction,
+        account_id: AccountId,
+        market_data: MarketData,
+        min_out: U128,
+    ) {
+        let client_echo =
+            near_sdk::serde_json::to_string(&Actions::SwapOutput { order_id }).unwrap();
         let action = Action::SwapAction {
             Swap: Swap {
                 pool_ids: vec![self.view_pair(&order.sell_token, &order.buy_token).pool_id],
                 output_token: order.sell_token.clone(),
-                min_output_amount: WBalance::from(0),
+                min_output_amount: min_out,
+                client_echo: Some(client_echo),
             },
         };
 
@@ -235,174 +471,180 @@ impl Contract {
             near_sdk::serde_json::to_string(&action).unwrap()
         );
 
-        ext_token::ext(order.buy_token.clone())
-            .with_static_gas(Gas(3))
-            .with_attached_deposit(1)
+        let buy_token = order.buy_token.clone();
+        let amount = U128(order.filled_buy_amount);
+        self.pending_swaps.insert(
+            &(order_id.0 as u64),
+            &PendingSwap {
+                order,
+                order_action,
+                account_id,
+                market_data,
+                output: None,
+                is_input_used: false,
+            },
+        );
+
+        ext_token::ext(buy_token)
+            .with_static_gas(Gas::ONE_TERA * 50u64)
+            .with_attached_deposit(ONE_YOCTO)
             .ft_transfer_call(
                 self.ref_finance_account.clone(),
-                WRatio::from(buy_amount),
+                amount,
                 Some("Swap".to_string()),
                 near_sdk::serde_json::to_string(&action).unwrap(),
             )
             .then(
                 ext_self::ext(current_account_id())
-                    .with_static_gas(Gas(20))
+                    .with_unused_gas_weight(100)
                     .with_attached_deposit(NO_DEPOSIT)
-                    .order_cancel_swap_callback(
-                        order_id,
-                        order,
-                        swap_fee,
-                        price_impact,
-                        order_action,
-                    ),
+                    .swap_result_callback(order_id),
             );
     }
 
-    #[private]
-    pub fn order_cancel_swap_callback(
-        &mut self,
-        order_id: U128,
-        order: Order,
-        swap_fee: U128,
-        price_impact: U128,
-        order_action: OrderAction,
-    ) {
-        log!(
-            "Order cancel swap callback attached gas: {}",
-            env::prepaid_gas().0
+    /// Records the swap output of the order sent back by ref finance.
+    pub fn on_swap_output(&mut self, order_id: U128, token: AccountId, amount: U128) {
+        let mut pending_swap = self
+            .pending_swaps
+            .get(&(order_id.0 as u64))
+            .unwrap_or_else(|| panic!("Swap for order with id: {} not found", order_id.0));
+
+        require!(
+            token == pending_swap.order.sell_token,
+            "Swap output token doesn't match the order sell token"
+        );
+        require!(
+            pending_swap.output.is_none(),
+            "Swap output has been already received"
         );
-        let market_id = self.tokens_markets.get(&order.sell_token).unwrap();
 
-        ext_market::ext(market_id)
-            .with_static_gas(Gas(20))
-            .with_attached_deposit(NO_DEPOSIT)
-            .view_market_data()
-            .then(
-                ext_self::ext(current_account_id())
-                    .with_static_gas(Gas(70))
-                    .with_attached_deposit(NO_DEPOSIT)
-                    .market_data_callback(order_id, order, swap_fee, price_impact, order_action),
-            );
+        pending_swap.output = Some(amount.0);
+        self.settle_swap(order_id, pending_swap);
     }
 
-    #[private]
-    pub fn market_data_callback(
+    /// Continues the order flow with the swap output once the swap is both resolved & its output
+    /// received, as these come in separate receipts in any order. Otherwise the swap is kept pending.
+    fn settle_swap(&mut self, order_id: U128, pending_swap: PendingSwap) {
+        match pending_swap.output {
+            Some(output) if pending_swap.is_input_used => {
+                self.pending_swaps.remove(&(order_id.0 as u64));
+                self.settle_order(
+                    order_id,
+                    pending_swap.order,
+                    pending_swap.order_action,
+                    pending_swap.account_id,
+                    pending_swap.market_data,
+                    U128(output),
+                );
+            }
+            _ => {
+                self.pending_swaps
+                    .insert(&(order_id.0 as u64), &pending_swap);
+            }
+        }
+    }
+
+    /// Finishes the cancel or liquidation flow with the sell token amount received for the position.
+    pub fn settle_order(
         &mut self,
         order_id: U128,
         order: Order,
-        swap_fee: U128,
-        price_impact: U128,
         order_action: OrderAction,
+        account_id: AccountId,
+        market_data: MarketData,
+        swap_output: U128,
     ) {
-        log!(
-            "Market data callback attached gas: {}",
-            env::prepaid_gas().0
-        );
-        require!(is_promise_success(), "failed to get market data.");
-        let market_data = match env::promise_result(0) {
-            PromiseResult::NotReady => panic!("failed to get market data"),
-            PromiseResult::Successful(val) => {
-                if let Ok(data) = near_sdk::serde_json::from_slice::<MarketData>(&val) {
-                    data
-                } else {
-                    panic!("failed parse market data")
-                }
-            }
-            PromiseResult::Failed => panic!("failed to get market data"),
-        };
-
-        if order_action == OrderAction::Cancel {
-            self.final_order_cancel(order_id, order, market_data, swap_fee, price_impact)
+        if order_action == OrderAction::Liquidate {
+            self.final_liquidate(order_id, order, account_id, market_data, swap_output);
         } else {
-            self.final_liquidate(order_id, order, market_data);
+            self.final_order_cancel(
+                order_id,
+                order,
+                order_action,
+                account_id,
+                market_data,
+                swap_output,
+            )
         }
     }
 
-    fn final_order_cancel(
+    /// Pays escrowed bounty of the canceled order to the keeper, who force closed it,
+    /// the same way as for execution. Otherwise bounty is returned to the owner.
+    fn settle_cancel_bounty(
         &mut self,
-        order_id: U128,
-        order: Order,
-        market_data: MarketData,
-        swap_fee: U128,
-        price_impact: U128,
+        order: &mut Order,
+        order_action: &OrderAction,
+        account_id: &AccountId,
     ) {
-        log!("Final order cancel attached gas: {}", env::prepaid_gas().0);
-
-        let mut order = order.clone();
-        let sell_amount = order.sell_token_price.value
-            * BigDecimal::from(U128::from(order.amount))
-            * order.leverage;
-
-        let pnl = self.calculate_pnl(signer_account_id(), order_id, market_data);
-
-        let expect_amount = self.get_price(order.buy_token.clone())
-            * sell_amount
-            * (BigDecimal::one() - BigDecimal::from(swap_fee))
-            * (BigDecimal::one() - BigDecimal::from(price_impact))
-            / order.buy_token_price.value;
-
-        self.increase_balance(
-            &signer_account_id(),
-            &order.sell_token,
-            expect_amount.round_u128(),
-        );
-
-        if pnl.is_profit && expect_amount > sell_amount + BigDecimal::from(pnl.amount) {
-            let protocol_profit = expect_amount - sell_amount - BigDecimal::from(pnl.amount);
-
-            let token_profit = self
-                .protocol_profit
-                .get(&order.sell_token)
-                .unwrap_or_default();
-            self.protocol_profit.insert(
-                &order.sell_token,
-                &(BigDecimal::from(token_profit) + protocol_profit),
-            );
+        match order_action {
+            OrderAction::ForceClose { keeper } => {
+                self.record_keeper_reward(keeper, KeeperAction::ForceClose, order, 0)
+            }
+            _ => {
+                let _ = self.refund_bounty(order, account_id);
+            }
         }
-
-        let mut orders = self.orders.get(&signer_account_id()).unwrap();
-        order.status = OrderStatus::Canceled;
-        orders.insert(order_id.0 as u64, order);
-        self.orders.insert(&signer_account_id(), &orders);
     }
 
-    pub fn repay(&self, order_id: U128, market_data: MarketData) {
-        let orders = self.orders.get(&signer_account_id()).unwrap_or_else(|| {
-            panic!("Orders for account: {} not found", signer_account_id());
-        });
-
-        let order = orders
-            .get(&(order_id.0 as u64))
-            .unwrap_or_else(|| {
-                panic!("Order with id: {} not found", order_id.0);
-            })
-            .clone();
-        let market_id = self.tokens_markets.get(&order.sell_token).unwrap();
-        let borrow_fee = BigDecimal::from(market_data.borrow_rate_ratio.0)
-            * BigDecimal::from((block_height() - order.block) as u128);
+    /// Starts the order cancel flow on behalf of the order owner.
+    pub fn close_order(
+        &mut self,
+        account_id: AccountId,
+        order_id: U128,
+        order: Order,
+        order_action: OrderAction,
+        min_out: U128,
+    ) {
+        let market_id = self.get_market_by(&order.sell_token);
+        self.set_order_status(order_id, OrderStatus::Closing);
 
-        ext_token::ext(order.sell_token.clone())
-            .with_static_gas(Gas::ONE_TERA * 35u64)
-            .with_attached_deposit(ONE_YOCTO)
-            .ft_transfer_call(
-                market_id,
-                U128(borrow_fee.round_u128()),
-                None,
-                "\"Repay\"".to_string(),
+        ext_ref_finance::ext(self.ref_finance_account.clone())
+            .with_unused_gas_weight(1)
+            .with_attached_deposit(NO_DEPOSIT)
+            .get_pool(self.view_pair(&order.sell_token, &order.buy_token).pool_id)
+            .and(
+                ext_market::ext(market_id)
+                    .with_static_gas(Gas::ONE_TERA * 5u64)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .view_market_data(),
             )
             .then(
                 ext_self::ext(current_account_id())
-                    .with_static_gas(Gas::ONE_TERA * 3u64)
+                    .with_unused_gas_weight(29)
                     .with_attached_deposit(NO_DEPOSIT)
-                    .repay_callback(),
+                    .get_pool_callback(order_id, order, order_action, account_id, min_out),
             );
     }
 
-    #[private]
-    pub fn repay_callback(&self) -> PromiseOrValue<U128> {
-        require!(is_promise_success(), "failed to repay assets");
-        //TODO: add repay success event
-        PromiseOrValue::Value(U128(0))
+    /// Returns borrowed amount of the order alongside with the borrow fee accrued since creation.
+    pub fn calculate_debt(&self, order: &Order, market_data: &MarketData) -> Balance {
+        if order.leverage <= BigDecimal::one() {
+            return 0;
+        }
+
+        let borrow_amount =
+            BigDecimal::from(U128(order.amount)) * (order.leverage - BigDecimal::one());
+        let borrow_fee = borrow_amount
+            * BigDecimal::from(market_data.borrow_rate_ratio)
+            * BigDecimal::from(block_height().saturating_sub(order.block));
+
+        WBalance::from(borrow_amount + borrow_fee).0
+    }
+
+    /// Repays the given amount of the order debt to the sell token market.
+    ///
+    /// Repay is recorded as pending operation, so it could be retried on failure.
+    pub fn repay(&mut self, account_id: &AccountId, order: &Order, amount: Balance) {
+        let operation = PendingOperation {
+            kind: OperationKind::Repay,
+            account_id: account_id.clone(),
+            token: order.sell_token.clone(),
+            amount: U128(amount),
+            reserved_balance: U128(0),
+        };
+        let operation_id = self.add_pending_operation(operation.clone());
+
+        self.send_repay(operation_id, &operation);
     }
 }
 
@@ -412,13 +654,13 @@ mod tests {
 
     use near_sdk::test_utils::test_env::alice;
     use near_sdk::test_utils::VMContextBuilder;
-    use near_sdk::{serde_json, testing_env, FunctionError, VMContext};
+    use near_sdk::{serde_json, testing_env, RuntimeFeesConfig, VMConfig, VMContext};
 
     fn get_context(is_view: bool) -> VMContext {
         VMContextBuilder::new()
             .current_account_id("margin.nearland.testnet".parse().unwrap())
             .signer_account_id(alice())
-            .predecessor_account_id("usdt_market.qa.nearland.testnet".parse().unwrap())
+            .predecessor_account_id(alice())
             .block_index(103930920)
             .block_timestamp(1)
             .is_view(is_view)
@@ -429,10 +671,8 @@ mod tests {
     fn test_order_was_canceled() {
         let context = get_context(false);
         testing_env!(context);
-        let mut contract = Contract::new_with_config(
-            "owner_id.testnet".parse().unwrap(),
-            "oracle_account_id.testnet".parse().unwrap(),
-        );
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
 
         contract.update_or_insert_price(
             "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
@@ -449,7 +689,7 @@ mod tests {
             },
         );
 
-        let order1 = "{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.01\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.22\"},\"block\":103930916,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#543\"}".to_string();
+        let order1 = "{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.01\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.22\"},\"block\":103930916,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#543\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
         contract.add_order(alice(), order1.clone());
 
         let order_id = U128(1);
@@ -470,6 +710,17 @@ mod tests {
             },
             block: 105210654,
             lpt_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#238".to_string(),
+            left_point: 0,
+            right_point: 40,
+            remaining_sell_amount: 0,
+            filled_buy_amount: 0,
+            filled_sell_amount: 0,
+            execution_block: 0,
+            execution_price: BigDecimal::zero(),
+            accrued_fee_x: 0,
+            accrued_fee_y: 0,
+            executor_bounty: 0,
+            bounty_token: None,
         };
 
         let market_data = MarketData {
@@ -481,12 +732,274 @@ mod tests {
             borrow_rate_ratio: U128(634273735391536),
         };
 
-        let swap_fee = U128(1);
-        let price_impact = U128(1);
-        contract.final_order_cancel(order_id, order, market_data, swap_fee, price_impact);
+        let swap_output = U128(1000000000000000000000000000);
+        contract.final_order_cancel(
+            order_id,
+            order,
+            OrderAction::Cancel,
+            alice(),
+            market_data,
+            swap_output,
+        );
 
         let orders = contract.orders.get(&alice()).unwrap();
         let order = orders.get(&1).unwrap();
         assert_eq!(order.status, OrderStatus::Canceled);
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            999700000000000000000000000
+        );
+        // closing fee of the swapped position is collected as protocol profit
+        assert_eq!(
+            contract
+                .protocol_profit
+                .get(&"usdt.qa.v1.nearlend.testnet".parse().unwrap())
+                .unwrap(),
+            300000000000000000000000
+        );
+    }
+
+    #[test]
+    fn test_pending_order_was_canceled_without_swap() {
+        let context = get_context(false);
+        testing_env!(context);
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+
+        contract.add_token_market(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+
+        let order1 = "{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"2\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.01\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.22\"},\"block\":103930916,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#543\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":100,\"bounty_token\":\"usdt.qa.v1.nearlend.testnet\"}".to_string();
+        contract.add_order(alice(), order1);
+        let order = contract.get_order_by(1).unwrap();
+
+        contract.final_pending_order_cancel(
+            U128(1),
+            order,
+            OrderAction::Cancel,
+            alice(),
+            MarketData::default(),
+            U128(1900000000000000000000000000),
+            U128(20000000000000000000000000),
+        );
+
+        // escrowed executor bounty is refunded to the user alongside with the removed liquidity
+        let orders = contract.orders.get(&alice()).unwrap();
+        assert_eq!(orders.get(&1).unwrap().status, OrderStatus::Canceled);
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            900000000000000000000000100
+        );
+        assert_eq!(
+            contract.balance_of(alice(), "wnear.qa.v1.nearlend.testnet".parse().unwrap()),
+            20000000000000000000000000
+        );
+    }
+
+    #[test]
+    fn test_force_closed_order_bounty_is_paid_to_keeper() {
+        let context = get_context(false);
+        testing_env!(context);
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+
+        contract.add_token_market(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+
+        let order1 = "{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.01\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.22\"},\"block\":103930916,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#543\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":100,\"bounty_token\":\"usdt.qa.v1.nearlend.testnet\"}".to_string();
+        contract.add_order(alice(), order1);
+        let order = contract.get_order_by(1).unwrap();
+
+        let keeper: AccountId = "keeper.testnet".parse().unwrap();
+        contract.final_pending_order_cancel(
+            U128(1),
+            order,
+            OrderAction::ForceClose {
+                keeper: keeper.clone(),
+            },
+            alice(),
+            MarketData::default(),
+            U128(1000000000000000000000000000),
+            U128(0),
+        );
+
+        // escrowed executor bounty is credited to the keeper instead of the owner
+        let orders = contract.orders.get(&alice()).unwrap();
+        assert_eq!(orders.get(&1).unwrap().status, OrderStatus::Canceled);
+        assert_eq!(orders.get(&1).unwrap().executor_bounty, 0);
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            1000000000000000000000000000
+        );
+
+        let stats = contract.view_keeper_stats(keeper);
+        assert_eq!(stats.liquidations, 1);
+        assert_eq!(
+            stats
+                .unclaimed_tokens
+                .get(&"usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            Some(&U128(100))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Order has to be Pending or Executed to be canceled")]
+    fn test_in_flight_order_cant_be_canceled() {
+        let context = get_context(false);
+        testing_env!(context);
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+
+        let order1 = "{\"status\":\"Executing\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.01\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.22\"},\"block\":103930916,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#543\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
+        contract.add_order(alice(), order1);
+
+        contract.cancel_order(U128(1), U128(0));
+    }
+
+    fn get_swap_contract() -> Contract {
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+        contract.add_token_market(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+        contract.insert_pair(TradePair {
+            sell_ticker_id: "usdt".to_string(),
+            sell_token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            sell_token_market: "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            buy_ticker_id: "wnear".to_string(),
+            buy_token: "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
+        });
+
+        let order1 = "{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.01\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.22\"},\"block\":103930916,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#543\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":240000000000000000000000000,\"filled_sell_amount\":1000000000000000000000000000,\"execution_block\":103930917,\"execution_price\":\"4.22\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
+        contract.add_order(alice(), order1);
+        contract
+    }
+
+    fn set_swap_result(result: PromiseResult) {
+        testing_env!(
+            get_context(false),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    #[test]
+    fn test_swap_output_is_accounted_per_order() {
+        testing_env!(get_context(false));
+        let mut contract = get_swap_contract();
+        let order = contract.get_order_by(1).unwrap();
+        contract.set_order_status(U128(1), OrderStatus::Closing);
+
+        contract.swap(
+            U128(1),
+            order,
+            OrderAction::Cancel,
+            alice(),
+            MarketData::default(),
+            U128(0),
+        );
+
+        // output may come before the swap is resolved, so it's kept until then
+        contract.on_swap_output(
+            U128(1),
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            U128(990000000000000000000000000),
+        );
+        let pending_swap = contract.pending_swaps.get(&1).unwrap();
+        assert_eq!(pending_swap.output, Some(990000000000000000000000000));
+        assert!(!pending_swap.is_input_used);
+
+        set_swap_result(PromiseResult::Successful(
+            serde_json::to_vec(&U128(240000000000000000000000000)).unwrap(),
+        ));
+        contract.swap_result_callback(U128(1));
+        assert!(contract.pending_swaps.get(&1).is_none());
+    }
+
+    #[test]
+    fn test_failed_swap_reverts_order() {
+        testing_env!(get_context(false));
+        let mut contract = get_swap_contract();
+        let order = contract.get_order_by(1).unwrap();
+        contract.set_order_status(U128(1), OrderStatus::Closing);
+
+        contract.swap(
+            U128(1),
+            order,
+            OrderAction::Cancel,
+            alice(),
+            MarketData::default(),
+            U128(0),
+        );
+
+        // refunded swap input means the swap failed on ref finance
+        set_swap_result(PromiseResult::Successful(
+            serde_json::to_vec(&U128(0)).unwrap(),
+        ));
+        contract.swap_result_callback(U128(1));
+
+        assert!(contract.pending_swaps.get(&1).is_none());
+        assert_eq!(
+            contract.get_order_by(1).unwrap().status,
+            OrderStatus::Executed
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap output token doesn't match the order sell token")]
+    fn test_swap_output_in_other_token_is_rejected() {
+        testing_env!(get_context(false));
+        let mut contract = get_swap_contract();
+        let order = contract.get_order_by(1).unwrap();
+
+        contract.swap(
+            U128(1),
+            order,
+            OrderAction::Cancel,
+            alice(),
+            MarketData::default(),
+            U128(0),
+        );
+        contract.on_swap_output(
+            U128(1),
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            U128(1),
+        );
+    }
+
+    #[test]
+    fn test_cancel_is_reverted_without_market_data() {
+        testing_env!(get_context(false));
+        let mut contract = get_swap_contract();
+        let order = contract.get_order_by(1).unwrap();
+        contract.set_order_status(U128(1), OrderStatus::Closing);
+
+        let pool_info = r#"{"pool_id":"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000","token_x":"usdt.qa.v1.nearlend.testnet","token_y":"wnear.qa.v1.nearlend.testnet","fee":2000,"point_delta":40,"current_point":-11333,"liquidity":"0","liquidity_x":"0","max_liquidity_per_point":"0","volume_x_in":"0","volume_y_in":"0","volume_x_out":"0","volume_y_out":"0","total_liquidity":"0","total_order_x":"0","total_order_y":"0","total_x":"0","total_y":"0","state":"Running"}"#;
+        testing_env!(
+            get_context(false),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(pool_info.as_bytes().to_vec()),
+                PromiseResult::Failed
+            ],
+        );
+        contract.get_pool_callback(U128(1), order, OrderAction::Cancel, alice(), U128(0));
+
+        // position isn't swapped, so the order is left as it was
+        assert!(contract.pending_swaps.get(&1).is_none());
+        assert_eq!(
+            contract.get_order_by(1).unwrap().status,
+            OrderStatus::Executed
+        );
     }
 }
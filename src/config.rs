@@ -1,5 +1,7 @@
+use crate::big_decimal::{WBalance, WRatio};
 use crate::*;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
 use near_sdk::serde::{Deserialize, Serialize};
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
@@ -12,8 +14,194 @@ pub struct Config {
     pub oracle_account_id: AccountId,
 }
 
+/// Every external account & risk/fee parameter of the contract
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConfigView {
+    pub owner_id: AccountId,
+    pub pending_owner: Option<AccountId>,
+    pub oracle_account_id: AccountId,
+    pub ref_finance_account: AccountId,
+    pub treasury_account: AccountId,
+    pub liquidation_threshold: WRatio,
+    pub volatility_rate: WRatio,
+    pub slippage_tolerance: WRatio,
+    pub min_executor_bounty: WBalance,
+    pub default_fees: FeeSchedule,
+    pub fee_tiers: Vec<FeeTier>,
+    pub pool_fee_owner_share: WRatio,
+    pub referral_share: WRatio,
+    pub timelock_delay: U64,
+    /// Fee schedules overridden for the supported pairs
+    pub pair_fees: Vec<((AccountId, AccountId), FeeSchedule)>,
+    /// Markets of the tokens used by the supported pairs
+    pub token_markets: Vec<(AccountId, AccountId)>,
+    pub paused: bool,
+    pub paused_pairs: Vec<(AccountId, AccountId)>,
+    pub paused_actions: Vec<PauseAction>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn view_config(&self) -> ConfigView {
+        let pairs = self.supported_markets.keys().collect::<Vec<_>>();
+
+        let pair_fees = pairs
+            .iter()
+            .filter_map(|pair| self.pair_fees.get(pair).map(|fees| (pair.clone(), fees)))
+            .collect();
+
+        // token markets lookup map can't be iterated, so they are collected for the pair tokens
+        let mut tokens: Vec<AccountId> = Vec::new();
+        for (sell_token, buy_token) in pairs {
+            for token in [sell_token, buy_token] {
+                if !tokens.contains(&token) {
+                    tokens.push(token);
+                }
+            }
+        }
+        let token_markets = tokens
+            .into_iter()
+            .filter_map(|token| {
+                self.tokens_markets
+                    .get(&token)
+                    .map(|market| (token, market))
+            })
+            .collect();
+
+        ConfigView {
+            owner_id: self.config.owner_id.clone(),
+            pending_owner: self.pending_owner.clone(),
+            oracle_account_id: self.config.oracle_account_id.clone(),
+            ref_finance_account: self.ref_finance_account.clone(),
+            treasury_account: self.treasury_account.clone(),
+            liquidation_threshold: U128(self.liquidation_threshold),
+            volatility_rate: WRatio::from(self.volatility_rate),
+            slippage_tolerance: U128(self.slippage_tolerance),
+            min_executor_bounty: U128(self.min_executor_bounty),
+            default_fees: self.default_fees.clone(),
+            fee_tiers: self.fee_tiers.clone(),
+            pool_fee_owner_share: U128(self.pool_fee_owner_share),
+            referral_share: U128(self.referral_share),
+            timelock_delay: U64(self.timelock_delay),
+            pair_fees,
+            token_markets,
+            paused: self.paused,
+            paused_pairs: self.paused_pairs.clone(),
+            paused_actions: self.paused_actions.clone(),
+        }
+    }
+}
+
 impl Contract {
     pub fn get_contract_config(&self) -> Config {
         self.config.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_init_with_accounts() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .build());
+        let contract = Contract::new_with_accounts(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+            "dcl.ref-labs.near".parse().unwrap(),
+            vec![(
+                "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+                "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            )],
+        );
+
+        let config = contract.view_config();
+        assert_eq!(config.ref_finance_account.as_str(), "dcl.ref-labs.near");
+        assert_eq!(config.volatility_rate, U128(95 * 10_u128.pow(22)));
+        assert_eq!(
+            contract.get_market_by(&"usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            "usdt_market.qa.v1.nearlend.testnet"
+                .parse::<AccountId>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "External account can't be the contract itself")]
+    fn test_contract_itself_cant_be_oracle() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .build());
+        Contract::new_with_accounts(
+            "owner_id.testnet".parse().unwrap(),
+            "margin.nearland.testnet".parse().unwrap(),
+            "dcl.ref-labs.near".parse().unwrap(),
+            vec![],
+        );
+    }
+
+    fn get_contract_with_pair() -> Contract {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .build());
+        let mut contract = Contract::new_with_accounts(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+            "dcl.ref-labs.near".parse().unwrap(),
+            vec![(
+                "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+                "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            )],
+        );
+        contract.insert_pair(TradePair {
+            sell_ticker_id: "usdt".to_string(),
+            sell_token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            sell_token_market: "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            buy_ticker_id: "wnear".to_string(),
+            buy_token: "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
+        });
+        contract
+    }
+
+    #[test]
+    fn test_view_config_lists_pair_parameters() {
+        let mut contract = get_contract_with_pair();
+        let pair: (AccountId, AccountId) = (
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+        contract.pair_fees.insert(&pair, &FeeSchedule::default());
+        contract.pause_pair(pair.0.clone(), pair.1.clone());
+        contract.pause_action(PauseAction::Deposit);
+
+        let config = contract.view_config();
+        assert_eq!(config.pair_fees.len(), 1);
+        assert_eq!(config.pair_fees[0].0, pair);
+        assert_eq!(
+            config.token_markets,
+            vec![(
+                pair.0.clone(),
+                "usdt_market.qa.v1.nearlend.testnet".parse().unwrap()
+            )]
+        );
+        assert_eq!(config.paused_pairs, vec![pair]);
+        assert_eq!(config.paused_actions, vec![PauseAction::Deposit]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Market of the token used by the supported pair can't be changed")]
+    fn test_market_of_pair_token_cant_be_changed() {
+        let mut contract = get_contract_with_pair();
+        contract.add_token_market(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "other_market.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+    }
+}
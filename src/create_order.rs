@@ -1,27 +1,42 @@
 use crate::big_decimal::{BigDecimal, WBalance};
+use crate::events::Events;
 use crate::ref_finance::ext_ref_finance;
 use crate::utils::{ext_market, ext_token, NO_DEPOSIT};
 use crate::*;
 use near_sdk::env::current_account_id;
-use near_sdk::{ext_contract, is_promise_success, serde_json, Gas, PromiseResult};
+use near_sdk::{ext_contract, is_promise_success, log, serde_json, Gas, PromiseResult};
 
 const GAS_FOR_BORROW: Gas = Gas(200_000_000_000_000);
+/// Covers the deposit withdrawal from ref finance alongside with its callback & the bounty refund on failure
+const GAS_FOR_ADD_LIQUIDITY_CALLBACK: Gas = Gas(90_000_000_000_000);
 
 #[ext_contract(ext_self)]
 trait ContractCallbackInterface {
-    fn get_pool_info_callback(&mut self, order: Order) -> PromiseOrValue<WBalance>;
+    fn get_pool_info_callback(
+        &mut self,
+        order: Order,
+        account_id: AccountId,
+    ) -> PromiseOrValue<WBalance>;
     fn borrow_callback(&mut self) -> PromiseOrValue<WBalance>;
-    fn add_liquidity_callback(&mut self, order: Order) -> PromiseOrValue<Balance>;
+    fn add_liquidity_callback(
+        &mut self,
+        order: Order,
+        operation_id: u64,
+    ) -> PromiseOrValue<Balance>;
 }
 
 #[near_bindgen]
 impl Contract {
     /// Creates an order with given order_type, amount, sell_token, buy_token & leverage.
+    /// Registers the referrer by `referral_code` if the user doesn't have one yet.
+    ///
+    /// Attached NEAR is escrowed as bounty for the order executor and refunded on cancel.
     ///
     /// Checks ref finance pool information for current price & borrow if leverage > 1.
     ///
     /// As far as we surpassed gas limit for contract call,
     /// borrow call was separated & made within batch of transaction alongside with Deposit & Add_Liquidity function
+    #[payable]
     pub fn create_order(
         &mut self,
         order_type: OrderType,
@@ -29,83 +44,124 @@ impl Contract {
         sell_token: AccountId,
         buy_token: AccountId,
         leverage: U128,
+        referral_code: Option<String>,
     ) -> PromiseOrValue<WBalance> {
-        let user = env::signer_account_id();
-
+        let executor_bounty = env::attached_deposit();
         require!(
-            self.balance_of(user.clone(), sell_token.clone()) >= amount.0,
-            "User doesn't have enough deposit to proceed this action"
+            executor_bounty >= self.min_executor_bounty,
+            format!(
+                "Attached executor bounty should be at least {} yoctoNEAR",
+                self.min_executor_bounty
+            )
         );
 
-        let order = Order {
-            status: OrderStatus::Pending,
-            order_type,
-            amount: Balance::from(amount),
-            sell_token: sell_token.clone(),
-            buy_token: buy_token.clone(),
-            leverage: BigDecimal::from(leverage),
-            sell_token_price: self.view_price(sell_token.clone()),
-            buy_token_price: self.view_price(buy_token.clone()),
-            block: env::block_height(),
-            lpt_id: "".to_string(),
-        };
+        let user = env::predecessor_account_id();
+        if let Some(referral_code) = referral_code {
+            self.apply_referral_code(&user, referral_code);
+        }
 
-        ext_ref_finance::ext(self.ref_finance_account.clone())
-            .with_attached_deposit(NO_DEPOSIT)
-            .with_static_gas(Gas::ONE_TERA * 5u64)
-            .get_pool(self.view_pair(&order.sell_token, &order.buy_token).pool_id)
-            .then(
-                ext_self::ext(current_account_id())
-                    .with_attached_deposit(NO_DEPOSIT)
-                    .with_static_gas((Gas::ONE_TERA * 200u64 + Gas::ONE_TERA * 50u64).into())
-                    .get_pool_info_callback(order),
-            )
-            .into()
+        self.place_order(
+            user,
+            order_type,
+            amount,
+            sell_token,
+            buy_token,
+            leverage,
+            executor_bounty,
+            None,
+        )
     }
 
     #[private]
-    pub fn get_pool_info_callback(&mut self, order: Order) -> PromiseOrValue<WBalance> {
-        require!(
-            is_promise_success(),
-            "Problem with pool on ref finance has occurred"
-        );
-
+    pub fn get_pool_info_callback(
+        &mut self,
+        order: Order,
+        account_id: AccountId,
+    ) -> PromiseOrValue<WBalance> {
+        let mut order = order;
         let pool_info = match env::promise_result(0) {
-            PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Successful(val) => {
-                if let Ok(pool) = serde_json::from_slice::<PoolInfo>(&val) {
-                    pool
-                } else {
-                    panic!("Some problem with pool parsing.")
+            PromiseResult::Successful(val) => serde_json::from_slice::<PoolInfo>(&val).ok(),
+            _ => None,
+        };
+
+        // balance could be spent while the flow was detached, so it's checked again
+        // to return the escrowed bounty instead of failing the callback
+        let failure = match &pool_info {
+            Some(pool_info) if pool_info.state == PoolState::Running => {
+                match self.balance_of(account_id.clone(), order.sell_token.clone()) >= order.amount
+                {
+                    true => None,
+                    false => Some("User doesn't have enough deposit, order wasn't created"),
                 }
             }
-            PromiseResult::Failed => panic!("Ref finance not found pool"),
+            _ => Some("Some problem with pool on ref finance, order wasn't created"),
         };
 
-        require!(
-            pool_info.state == PoolState::Running,
-            "Some problem with pool, please contact with ref finance to support."
-        );
-
-        self.add_liquidity(pool_info, order)
+        match (pool_info, failure) {
+            (Some(pool_info), None) => self.add_liquidity(pool_info, order, account_id),
+            (_, failure) => {
+                log!("{}", failure.unwrap_or_default());
+                match self.refund_bounty(&mut order, &account_id) {
+                    Some(refund) => refund.into(),
+                    None => PromiseOrValue::Value(U128(0)),
+                }
+            }
+        }
     }
 
     /// Makes batch of transaction consist of Deposit & Add_Liquidity
-    fn add_liquidity(&mut self, pool_info: PoolInfo, order: Order) -> PromiseOrValue<WBalance> {
+    ///
+    /// User balance is reserved & the deposit is recorded as pending operation,
+    /// so tokens could be returned from ref finance if adding liquidity fails.
+    /// Opening fee is reserved alongside and taken once the liquidity is added.
+    fn add_liquidity(
+        &mut self,
+        pool_info: PoolInfo,
+        order: Order,
+        account_id: AccountId,
+    ) -> PromiseOrValue<WBalance> {
+        let mut order = order;
         // calculating the range for the liquidity to be added into
         // consider the smallest gap is point_delta for given pool
-        let mut left_point = pool_info.current_point as i32;
+        // token x liquidity is placed above the current point & token y liquidity below it
+        let point_delta = pool_info.point_delta as i32;
+        let is_sell_token_x = pool_info.token_x == order.sell_token;
+        let (left_point, right_point) = if is_sell_token_x {
+            let mut left_point = pool_info.current_point as i32;
+            while left_point % point_delta != 0 {
+                left_point += 1;
+            }
+            (left_point, left_point + point_delta)
+        } else {
+            let mut right_point = pool_info.current_point as i32;
+            while right_point % point_delta != 0 {
+                right_point -= 1;
+            }
+            (right_point - point_delta, right_point)
+        };
 
-        while left_point % pool_info.point_delta as i32 != 0 {
-            left_point += 1;
-        }
+        order.left_point = left_point;
+        order.right_point = right_point;
 
-        let right_point = left_point + pool_info.point_delta as i32;
+        let reserved_amount = order.amount;
+        let fees = self.get_account_fees(&account_id, &order.sell_token, &order.buy_token);
+        order.amount -= Self::calculate_fee(order.amount, fees.opening_fee);
 
         let amount = U128::from(BigDecimal::from(U128::from(order.amount)) * order.leverage);
 
-        let amount_x: WBalance = amount;
-        let amount_y = U128::from(0);
+        self.decrease_balance(&account_id, &order.sell_token, reserved_amount);
+        let operation_id = self.add_pending_operation(PendingOperation {
+            kind: OperationKind::AddLiquidity,
+            account_id,
+            token: order.sell_token.clone(),
+            amount,
+            reserved_balance: U128(reserved_amount),
+        });
+
+        let (amount_x, amount_y): (WBalance, WBalance) = match is_sell_token_x {
+            true => (amount, U128::from(0)),
+            false => (U128::from(0), amount),
+        };
         let min_amount_x = U128::from(0);
         let min_amount_y = U128::from(0);
 
@@ -134,44 +190,87 @@ impl Contract {
             )
             .then(
                 ext_self::ext(current_account_id())
-                    .with_static_gas(Gas::ONE_TERA * 2u64)
+                    .with_static_gas(GAS_FOR_ADD_LIQUIDITY_CALLBACK)
                     .with_attached_deposit(NO_DEPOSIT)
-                    .add_liquidity_callback(order.clone()),
+                    .add_liquidity_callback(order.clone(), operation_id),
             );
         add_liquidity_promise.into()
     }
 
     #[private]
-    pub fn add_liquidity_callback(&mut self, mut order: Order) -> PromiseOrValue<WBalance> {
+    pub fn add_liquidity_callback(
+        &mut self,
+        order: Order,
+        operation_id: u64,
+    ) -> PromiseOrValue<WBalance> {
         require!(
             env::promise_results_count() == 2,
             "Contract expected 2 results on the callback"
         );
-        match env::promise_result(0) {
-            PromiseResult::NotReady | PromiseResult::Failed => {
-                panic!("failed to deposit liquidity")
-            }
-            _ => (),
+
+        let mut order = order;
+        let deposited_amount = match env::promise_result(0) {
+            PromiseResult::Successful(result) => serde_json::from_slice::<U128>(&result)
+                .map(|amount| amount.0)
+                .unwrap_or(0),
+            _ => 0,
         };
 
-        self.decrease_balance(
-            &env::signer_account_id().clone(),
-            &order.sell_token.clone(),
-            order.amount,
-        );
+        let lpt_id = match env::promise_result(1) {
+            PromiseResult::Successful(result) => serde_json::from_slice::<String>(&result).ok(),
+            _ => None,
+        };
 
-        let lpt_id: String = match env::promise_result(1) {
-            PromiseResult::Successful(result) => {
-                serde_json::from_slice::<String>(&result).unwrap().into()
+        match lpt_id {
+            Some(lpt_id) => {
+                let operation = self.pending_operations.remove(&operation_id).unwrap();
+                order.lpt_id = lpt_id;
+                self.collect_trading_fee(
+                    &operation.account_id,
+                    &order.sell_token,
+                    operation.reserved_balance.0 - order.amount,
+                );
+
+                self.order_nonce += 1;
+                let order_id = self.order_nonce;
+                self.insert_order_for_user(&operation.account_id, order, order_id);
             }
-            _ => panic!("failed to add liquidity"),
-        };
+            None if deposited_amount == 0 => {
+                let account_id = self
+                    .pending_operations
+                    .get(&operation_id)
+                    .unwrap()
+                    .account_id;
+                let _ = self.refund_bounty(&mut order, &account_id);
 
-        order.lpt_id = lpt_id;
+                // deposit was refunded by the token, so only user balance has to be restored
+                log!(
+                    "{}",
+                    Events::OperationFailed(
+                        operation_id,
+                        OperationKind::AddLiquidity,
+                        "failed to deposit liquidity".to_string()
+                    )
+                );
+                self.recover_operation(operation_id);
+            }
+            None => {
+                log!(
+                    "{}",
+                    Events::OperationFailed(
+                        operation_id,
+                        OperationKind::AddLiquidity,
+                        "failed to add liquidity".to_string()
+                    )
+                );
+                let mut operation = self.pending_operations.get(&operation_id).unwrap();
+                let _ = self.refund_bounty(&mut order, &operation.account_id);
+                operation.amount = U128(deposited_amount);
+                self.pending_operations.insert(&operation_id, &operation);
 
-        self.order_nonce += 1;
-        let order_id = self.order_nonce;
-        self.insert_order_for_user(&env::signer_account_id(), order, order_id);
+                return self.withdraw_from_ref(operation_id, &operation).into();
+            }
+        }
 
         PromiseOrValue::Value(U128(0))
     }
@@ -190,7 +289,7 @@ impl Contract {
         );
 
         require!(
-            self.balance_of(env::signer_account_id(), token.clone()) >= amount.0,
+            self.balance_of(env::predecessor_account_id(), token.clone()) >= amount.0,
             "User doesn't have enough deposit to proceed this action"
         );
 
@@ -233,3 +332,182 @@ impl Contract {
         self.orders.insert(&account_id, &user_orders_by_id);
     }
 }
+
+impl Contract {
+    /// Places the order on behalf of the user from its deposited balance.
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_order(
+        &mut self,
+        user: AccountId,
+        order_type: OrderType,
+        amount: WBalance,
+        sell_token: AccountId,
+        buy_token: AccountId,
+        leverage: U128,
+        executor_bounty: Balance,
+        bounty_token: Option<AccountId>,
+    ) -> PromiseOrValue<WBalance> {
+        self.assert_not_paused(PauseAction::CreateOrder, Some((&sell_token, &buy_token)));
+        require!(
+            !self.is_pair_reduce_only(&sell_token, &buy_token),
+            "Pair is in reduce-only mode"
+        );
+        require!(
+            self.balance_of(user.clone(), sell_token.clone()) >= amount.0,
+            "User doesn't have enough deposit to proceed this action"
+        );
+
+        let order = Order {
+            status: OrderStatus::Pending,
+            order_type,
+            amount: Balance::from(amount),
+            sell_token: sell_token.clone(),
+            buy_token: buy_token.clone(),
+            leverage: BigDecimal::from(leverage),
+            sell_token_price: self.view_price(sell_token.clone()),
+            buy_token_price: self.view_price(buy_token.clone()),
+            block: env::block_height(),
+            lpt_id: "".to_string(),
+            left_point: 0,
+            right_point: 0,
+            remaining_sell_amount: 0,
+            filled_buy_amount: 0,
+            filled_sell_amount: 0,
+            execution_block: 0,
+            execution_price: BigDecimal::zero(),
+            accrued_fee_x: 0,
+            accrued_fee_y: 0,
+            executor_bounty,
+            bounty_token,
+        };
+
+        ext_ref_finance::ext(self.ref_finance_account.clone())
+            .with_attached_deposit(NO_DEPOSIT)
+            .with_static_gas(Gas::ONE_TERA * 5u64)
+            .get_pool(self.view_pair(&order.sell_token, &order.buy_token).pool_id)
+            .then(
+                ext_self::ext(current_account_id())
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .with_static_gas((Gas::ONE_TERA * 200u64 + Gas::ONE_TERA * 50u64).into())
+                    .get_pool_info_callback(order, user),
+            )
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, VMContext};
+
+    fn get_context(prepaid_gas: Gas) -> VMContext {
+        VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .predecessor_account_id("margin.nearland.testnet".parse().unwrap())
+            .prepaid_gas(prepaid_gas)
+            .build()
+    }
+
+    fn get_order(executor_bounty: Balance) -> Order {
+        let order: Order = serde_json::from_str("{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":\"usdt.qa.v1.nearlend.testnet\"}").unwrap();
+        Order {
+            executor_bounty,
+            ..order
+        }
+    }
+
+    #[test]
+    fn test_failed_deposit_refunds_token_bounty() {
+        testing_env!(get_context(GAS_FOR_ADD_LIQUIDITY_CALLBACK));
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+        let operation_id = contract.add_pending_operation(PendingOperation {
+            kind: OperationKind::AddLiquidity,
+            account_id: alice(),
+            token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            amount: U128(1000),
+            reserved_balance: U128(1000),
+        });
+
+        testing_env!(
+            get_context(GAS_FOR_ADD_LIQUIDITY_CALLBACK),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed, PromiseResult::Failed],
+        );
+        let _ = contract.add_liquidity_callback(get_order(100), operation_id);
+
+        // reserved order amount & the escrowed bounty are returned to the user balance
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            1100
+        );
+        assert!(contract.pending_operations.get(&operation_id).is_none());
+    }
+
+    #[test]
+    fn test_order_without_balance_refunds_token_bounty() {
+        testing_env!(get_context(GAS_FOR_ADD_LIQUIDITY_CALLBACK));
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+
+        // order amount was spent while the creation flow was detached
+        testing_env!(
+            get_context(GAS_FOR_ADD_LIQUIDITY_CALLBACK),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                r#"{"pool_id":"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000","token_x":"usdt.qa.v1.nearlend.testnet","token_y":"wnear.qa.v1.nearlend.testnet","fee":2000,"point_delta":40,"current_point":-11333,"liquidity":"0","liquidity_x":"0","max_liquidity_per_point":"0","volume_x_in":"0","volume_y_in":"0","volume_x_out":"0","volume_y_out":"0","total_liquidity":"0","total_order_x":"0","total_order_y":"0","total_x":"0","total_y":"0","state":"Running"}"#
+                    .as_bytes()
+                    .to_vec()
+            )],
+        );
+        let _ = contract.get_pool_info_callback(get_order(100), alice());
+
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            100
+        );
+        assert!(contract.view_pending_operations().is_empty());
+    }
+
+    #[test]
+    fn test_failed_add_liquidity_withdraws_deposit_within_callback_gas() {
+        testing_env!(get_context(GAS_FOR_ADD_LIQUIDITY_CALLBACK));
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+
+        let operation_id = contract.add_pending_operation(PendingOperation {
+            kind: OperationKind::AddLiquidity,
+            account_id: alice(),
+            token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            amount: U128(1000),
+            reserved_balance: U128(1000),
+        });
+
+        // deposit succeeded while adding liquidity failed, so the deposit is withdrawn
+        testing_env!(
+            get_context(GAS_FOR_ADD_LIQUIDITY_CALLBACK),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(serde_json::to_vec(&U128(1000)).unwrap()),
+                PromiseResult::Failed
+            ],
+        );
+        let _ = contract.add_liquidity_callback(get_order(100), operation_id);
+
+        let operation = contract.pending_operations.get(&operation_id).unwrap();
+        assert_eq!(operation.amount, U128(1000));
+        // escrowed bounty is returned right away, while the order amount after the withdrawal
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            100
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::*;
+
+/// Period owners are able to cancel open orders of the delisted pair themselves, 3 days
+const DELISTING_GRACE_PERIOD: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl Contract {
+    /// Switches the pair to reduce-only mode, so new orders can't be created.
+    /// Open orders could be canceled by their owners within the grace period
+    /// and force closed by keepers after it.
+    pub fn delist_pair(&mut self, sell_token: AccountId, buy_token: AccountId) {
+        self.assert_role(Role::Admin);
+        let pair_data = self.view_pair(&sell_token, &buy_token);
+        let pair = (sell_token, buy_token);
+        require!(
+            self.pair_delistings.get(&pair).is_none(),
+            "Pair is already being delisted"
+        );
+
+        let started_at = env::block_timestamp();
+        self.pair_delistings.insert(
+            &pair,
+            &PairDelisting {
+                pair_data,
+                started_at,
+                force_close_from: started_at + DELISTING_GRACE_PERIOD,
+                removed_at: None,
+            },
+        );
+    }
+
+    /// Returns the pair being delisted back to normal mode.
+    pub fn cancel_delisting(&mut self, sell_token: AccountId, buy_token: AccountId) {
+        self.assert_role(Role::Admin);
+        require!(
+            self.is_pair_reduce_only(&sell_token, &buy_token),
+            "Pair is not being delisted"
+        );
+        self.pair_delistings.remove(&(sell_token, buy_token));
+    }
+
+    /// Closes the open order of the delisted pair on behalf of its owner once the grace period is over.
+    /// Position is swapped back with the oracle price based minimal output & order funds
+    /// are returned to the owner balance, while escrowed bounty is paid to the keeper.
+    pub fn force_close_order(&mut self, order_id: U128) {
+        let account_id = self
+            .get_account_by(order_id.0)
+            .unwrap_or_else(|| panic!("Order with id: {} not found", order_id.0));
+        let order = self.get_order_by(order_id.0).unwrap();
+        require!(
+            self.is_order_open(&order),
+            "Order has to be Pending or Executed to be force closed"
+        );
+
+        let delisting = self
+            .pair_delistings
+            .get(&(order.sell_token.clone(), order.buy_token.clone()))
+            .filter(|delisting| delisting.removed_at.is_none())
+            .unwrap_or_else(|| panic!("Pair of the order {} is not being delisted", order_id.0));
+        require!(
+            env::block_timestamp() >= delisting.force_close_from,
+            format!(
+                "Orders of the pair could be force closed since {}",
+                delisting.force_close_from
+            )
+        );
+        self.assert_not_paused(
+            PauseAction::CancelOrder,
+            Some((&order.sell_token, &order.buy_token)),
+        );
+
+        let min_out = self.calculate_swap_floor(&order);
+        let order_action = OrderAction::ForceClose {
+            keeper: env::predecessor_account_id(),
+        };
+        self.close_order(account_id, order_id, order, order_action, min_out);
+    }
+
+    /// Removes the delisted pair once all its orders are settled. Could be called by anyone.
+    pub fn finalize_delisting(&mut self, sell_token: AccountId, buy_token: AccountId) {
+        let pair = (sell_token.clone(), buy_token.clone());
+        let mut delisting = self
+            .pair_delistings
+            .get(&pair)
+            .filter(|delisting| delisting.removed_at.is_none())
+            .unwrap_or_else(|| panic!("Pair {}/{} is not being delisted", sell_token, buy_token));
+
+        self.remove_pair(sell_token, buy_token);
+
+        delisting.removed_at = Some(env::block_timestamp());
+        self.pair_delistings.insert(&pair, &delisting);
+    }
+
+    /// Returns pairs being delisted & already removed ones alongside with their settlement status.
+    pub fn view_delisted_pairs(&self) -> Vec<DelistingView> {
+        self.pair_delistings
+            .values()
+            .map(|delisting| {
+                let status = match delisting.removed_at {
+                    Some(_) => DelistingStatus::Delisted,
+                    None if env::block_timestamp() >= delisting.force_close_from => {
+                        DelistingStatus::ForceClose
+                    }
+                    None => DelistingStatus::ReduceOnly,
+                };
+
+                DelistingView {
+                    unsettled_orders: self.count_unsettled_orders(
+                        &delisting.pair_data.sell_token,
+                        &delisting.pair_data.buy_token,
+                    ),
+                    pair_data: delisting.pair_data,
+                    status,
+                    started_at: delisting.started_at,
+                    force_close_from: delisting.force_close_from,
+                    removed_at: delisting.removed_at,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    pub fn is_pair_reduce_only(&self, sell_token: &AccountId, buy_token: &AccountId) -> bool {
+        self.pair_delistings
+            .get(&(sell_token.clone(), buy_token.clone()))
+            .map(|delisting| delisting.removed_at.is_none())
+            .unwrap_or(false)
+    }
+
+    /// Counts orders of the pair, which are not canceled or liquidated yet.
+    pub fn count_unsettled_orders(&self, sell_token: &AccountId, buy_token: &AccountId) -> u64 {
+        self.orders
+            .values()
+            .flat_map(|orders| orders.into_values())
+            .filter(|order| {
+                order.sell_token == *sell_token
+                    && order.buy_token == *buy_token
+                    && order.status != OrderStatus::Canceled
+                    && order.status != OrderStatus::Liquidated
+            })
+            .count() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{serde_json, testing_env};
+
+    fn set_context(block_timestamp: u64) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .block_timestamp(block_timestamp)
+            .build());
+    }
+
+    #[test]
+    fn test_pair_is_removed_once_orders_are_settled() {
+        set_context(0);
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let sell_token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        let buy_token: AccountId = "wnear.qa.v1.nearlend.testnet".parse().unwrap();
+        contract.insert_pair(TradePair {
+            sell_ticker_id: "usdt".to_string(),
+            sell_token: sell_token.clone(),
+            sell_token_market: "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            buy_ticker_id: "wnear".to_string(),
+            buy_token: buy_token.clone(),
+            pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
+        });
+        let order: Order = serde_json::from_str("{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}").unwrap();
+        contract.insert_order_for_user(&alice(), order, 0);
+
+        contract.delist_pair(sell_token.clone(), buy_token.clone());
+        assert!(contract.is_pair_reduce_only(&sell_token, &buy_token));
+
+        set_context(DELISTING_GRACE_PERIOD);
+        let delisted_pairs = contract.view_delisted_pairs();
+        assert_eq!(delisted_pairs[0].status, DelistingStatus::ForceClose);
+        assert_eq!(delisted_pairs[0].unsettled_orders, 1);
+
+        contract.set_order_status(U128(0), OrderStatus::Canceled);
+        contract.finalize_delisting(sell_token.clone(), buy_token.clone());
+
+        let delisted_pairs = contract.view_delisted_pairs();
+        assert_eq!(delisted_pairs[0].status, DelistingStatus::Delisted);
+        assert_eq!(delisted_pairs[0].unsettled_orders, 0);
+        assert!(contract.view_supported_pairs().is_empty());
+        assert!(!contract.is_pair_reduce_only(&sell_token, &buy_token));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pair has unsettled orders, it should be delisted first")]
+    fn test_pair_with_open_orders_cant_be_removed() {
+        set_context(0);
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let order: Order = serde_json::from_str("{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}").unwrap();
+        contract.insert_order_for_user(&alice(), order, 0);
+
+        contract.remove_pair(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+    }
+}
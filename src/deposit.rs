@@ -4,11 +4,29 @@ use near_sdk::{Gas, PromiseOrValue};
 
 const GAS_FOR_DEPOSIT: Gas = Gas(2_000_000_000_000);
 
+#[near_bindgen]
+impl Contract {
+    /// Moves deposited tokens from the caller balance to the receiver balance
+    /// without transferring them on the token contract.
+    pub fn transfer_internal(&mut self, receiver: AccountId, token: AccountId, amount: WBalance) {
+        let sender = env::predecessor_account_id();
+        require!(amount.0 > 0, "Amount should be a positive number");
+        require!(
+            sender != receiver,
+            "Sender and receiver should be different"
+        );
+
+        self.decrease_balance(&sender, &token, amount.0);
+        self.increase_balance(&receiver, &token, amount.0);
+    }
+}
+
 impl Contract {
     /// Accepts tokens.
-    /// Updates user balance
+    /// Updates balance of the account tokens were transferred from
     pub fn deposit(
         &mut self,
+        account_id: AccountId,
         token_amount: WBalance,
         token: AccountId,
     ) -> PromiseOrValue<WBalance> {
@@ -16,6 +34,7 @@ impl Contract {
             env::prepaid_gas() >= GAS_FOR_DEPOSIT,
             "Prepaid gas is not enough for deposit flow"
         );
+        self.assert_not_paused(PauseAction::Deposit, None);
 
         let is_token_supported = self
             .supported_markets
@@ -27,7 +46,7 @@ impl Contract {
             "Deposit was done by token, that are not currently supported"
         );
 
-        self.increase_balance(&env::signer_account_id(), &token, token_amount.0);
+        self.increase_balance(&account_id, &token, token_amount.0);
 
         PromiseOrValue::Value(U128(0))
     }
@@ -74,6 +93,8 @@ impl Contract {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
     use std::str::FromStr;
 
     pub const INITIAL_BALANCE: Balance = 10_000;
@@ -130,4 +151,21 @@ mod tests {
 
         contract.decrease_balance(&user.clone(), &token.clone(), 10000 * AMOUNT_TO_DECREASE);
     }
+
+    #[test]
+    fn test_transfer_internal() {
+        let (mut contract, user, token) = get_contract();
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(user.clone())
+            .build());
+
+        let receiver: AccountId = AccountId::from_str("some_example_receiver.testnet").unwrap();
+        contract.transfer_internal(receiver.clone(), token.clone(), U128(AMOUNT_TO_DECREASE));
+
+        assert_eq!(
+            contract.balance_of(user, token.clone()),
+            INITIAL_BALANCE - AMOUNT_TO_DECREASE
+        );
+        assert_eq!(contract.balance_of(receiver, token), AMOUNT_TO_DECREASE);
+    }
 }
@@ -0,0 +1,69 @@
+use crate::*;
+use std::fmt;
+use std::fmt::Formatter;
+
+pub enum Events {
+    /// Order flow has failed & order was restored with its prior status
+    OrderFlowFailed(U128, String),
+    /// Pending operation has failed on the external contract
+    OperationFailed(u64, OperationKind, String),
+    /// Tokens of the failed operation were returned & balances restored
+    OperationRecovered(u64, OperationKind),
+    /// Parameter change was proposed & could be applied since the given timestamp
+    ParameterChangeProposed(u64, ParameterChange, u64),
+    /// Parameter change proposal was cancelled by the guardian
+    ParameterChangeCancelled(u64),
+    /// Parameter change proposal was applied
+    ParameterChangeApplied(u64),
+}
+
+impl fmt::Display for Events {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Events::OrderFlowFailed(order_id, reason) => {
+                write!(
+                    f,
+                    r#"EVENT_JSON:{{"standard": "nep297", "version": "1.0.0", "event": "OrderFlowFailed", "data": {{"order_id": "{}", "reason": "{}"}}}}"#,
+                    order_id.0, reason
+                )
+            }
+            Events::OperationFailed(operation_id, kind, reason) => {
+                write!(
+                    f,
+                    r#"EVENT_JSON:{{"standard": "nep297", "version": "1.0.0", "event": "OperationFailed", "data": {{"operation_id": "{}", "kind": "{:?}", "reason": "{}"}}}}"#,
+                    operation_id, kind, reason
+                )
+            }
+            Events::OperationRecovered(operation_id, kind) => {
+                write!(
+                    f,
+                    r#"EVENT_JSON:{{"standard": "nep297", "version": "1.0.0", "event": "OperationRecovered", "data": {{"operation_id": "{}", "kind": "{:?}"}}}}"#,
+                    operation_id, kind
+                )
+            }
+            Events::ParameterChangeProposed(proposal_id, change, executable_at) => {
+                write!(
+                    f,
+                    r#"EVENT_JSON:{{"standard": "nep297", "version": "1.0.0", "event": "ParameterChangeProposed", "data": {{"proposal_id": "{}", "change": {}, "executable_at": "{}"}}}}"#,
+                    proposal_id,
+                    near_sdk::serde_json::to_string(change).unwrap_or_default(),
+                    executable_at
+                )
+            }
+            Events::ParameterChangeCancelled(proposal_id) => {
+                write!(
+                    f,
+                    r#"EVENT_JSON:{{"standard": "nep297", "version": "1.0.0", "event": "ParameterChangeCancelled", "data": {{"proposal_id": "{}"}}}}"#,
+                    proposal_id
+                )
+            }
+            Events::ParameterChangeApplied(proposal_id) => {
+                write!(
+                    f,
+                    r#"EVENT_JSON:{{"standard": "nep297", "version": "1.0.0", "event": "ParameterChangeApplied", "data": {{"proposal_id": "{}"}}}}"#,
+                    proposal_id
+                )
+            }
+        }
+    }
+}
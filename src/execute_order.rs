@@ -1,20 +1,38 @@
-use crate::ref_finance::ext_ref_finance;
+use crate::events::Events;
+use crate::ref_finance::{ext_ref_finance, LiquidityInfo};
 use crate::utils::NO_DEPOSIT;
 use crate::*;
 use near_sdk::env::current_account_id;
-use near_sdk::{ext_contract, is_promise_success, Gas, Promise, PromiseResult};
+use near_sdk::{ext_contract, log, serde_json, Gas, Promise, PromiseResult};
+
+const GAS_FOR_EXECUTE_ORDER: Gas = Gas(150_000_000_000_000);
 
 #[ext_contract(ext_self)]
 trait ContractCallbackInterface {
-    fn remove_liquidity_for_execute_order_callback(&self, order: Order, order_id: U128);
-    fn execute_order_callback(&self, order: Order, order_id: U128);
+    fn remove_liquidity_for_execute_order_callback(
+        &mut self,
+        order: Order,
+        order_id: U128,
+        executor_id: AccountId,
+        unclaimed_fee_x: U128,
+        unclaimed_fee_y: U128,
+    );
+    fn execute_order_callback(&mut self, order: Order, order_id: U128, executor_id: AccountId);
 }
 
 #[near_bindgen]
 impl Contract {
     /// Executes order by inner order_id set on ref finance once the price range was crossed.
-    /// Gets pool info, removes liquidity presented by one asset and marks order as executed.
-    pub fn execute_order(&self, order_id: U128) -> PromiseOrValue<U128> {
+    /// Gets liquidity & pool info, verifies the order range was crossed by the pool current point,
+    /// removes liquidity and marks order as executed with the actually received amounts.
+    ///
+    /// Order is marked as `Executing` until the flow is finished, so it can't be processed concurrently.
+    pub fn execute_order(&mut self, order_id: U128) {
+        require!(
+            env::prepaid_gas() >= GAS_FOR_EXECUTE_ORDER,
+            "Prepaid gas is not enough for execute order flow"
+        );
+
         let order = self.get_order_by(order_id.0);
         require!(order.is_some(), "There is no such order to be executed");
 
@@ -25,54 +43,106 @@ impl Contract {
         );
 
         let order = order.unwrap().clone();
+        self.assert_not_paused(
+            PauseAction::ExecuteOrder,
+            Some((&order.sell_token, &order.buy_token)),
+        );
+        self.set_order_status(order_id, OrderStatus::Executing);
 
         ext_ref_finance::ext(self.ref_finance_account.clone())
             .with_static_gas(Gas::ONE_TERA * 5u64)
             .with_attached_deposit(NO_DEPOSIT)
             .get_liquidity(order.lpt_id.clone())
+            .and(
+                ext_ref_finance::ext(self.ref_finance_account.clone())
+                    .with_static_gas(Gas::ONE_TERA * 5u64)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .get_pool(self.view_pair(&order.sell_token, &order.buy_token).pool_id),
+            )
             .then(
                 ext_self::ext(current_account_id())
                     .with_unused_gas_weight(100)
                     .with_attached_deposit(NO_DEPOSIT)
-                    .execute_order_callback(order, order_id),
-            )
-            .into()
+                    .execute_order_callback(order, order_id, env::predecessor_account_id()),
+            );
     }
 
     #[private]
-    pub fn execute_order_callback(&self, order: Order, order_id: U128) -> PromiseOrValue<U128> {
-        require!(is_promise_success(), "Failed to get_liquidity");
+    pub fn execute_order_callback(&mut self, order: Order, order_id: U128, executor_id: AccountId) {
+        require!(
+            env::promise_results_count() == 2,
+            "Contract expected 2 results on the callback"
+        );
 
         let position = match env::promise_result(0) {
-            PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Successful(val) => {
-                near_sdk::serde_json::from_slice::<crate::ref_finance::LiquidityInfo>(&val).unwrap()
+            PromiseResult::Successful(val) => serde_json::from_slice::<LiquidityInfo>(&val).ok(),
+            _ => None,
+        };
+        let pool_info = match env::promise_result(1) {
+            PromiseResult::Successful(val) => serde_json::from_slice::<PoolInfo>(&val).ok(),
+            _ => None,
+        };
+
+        let (position, pool_info) = match (position, pool_info) {
+            (Some(position), Some(pool_info)) => (position, pool_info),
+            _ => {
+                self.revert_order(order_id, order, "Failed to get liquidity or pool info");
+                return;
             }
-            PromiseResult::Failed => panic!("Ref finance not found pool"),
         };
 
-        let remove_liquidity_amount = position.amount;
+        // range of the order migrated from the previous layout isn't known, so ref finance one is used
+        let mut order = order;
+        if order.left_point == order.right_point {
+            order.left_point = position.left_point;
+            order.right_point = position.right_point;
+        }
 
-        let min_amount_x = 0;
-        let min_amount_y =
-            BigDecimal::from(order.amount) * order.leverage * order.sell_token_price.value
-                / order.buy_token_price.value;
+        if position.left_point != order.left_point || position.right_point != order.right_point {
+            self.revert_order(
+                order_id,
+                order,
+                "Liquidity range doesn't match the order range",
+            );
+            return;
+        }
+
+        let current_point = pool_info.current_point as i32;
+        if self.crossed_points(&order, current_point) == 0 {
+            self.revert_order(
+                order_id,
+                order,
+                "Order range wasn't crossed by the market yet",
+            );
+            return;
+        }
+
+        let (min_amount_x, min_amount_y) = self.map_pool_amounts(
+            &order,
+            U128(0),
+            self.calculate_execution_floor(&order, current_point),
+        );
 
         ext_ref_finance::ext(self.ref_finance_account.clone())
             .with_static_gas(Gas::ONE_TERA * 100u64)
             .remove_liquidity(
                 order.lpt_id.clone(),
-                remove_liquidity_amount,
-                U128(min_amount_x),
-                U128::from(min_amount_y),
+                position.amount,
+                min_amount_x,
+                min_amount_y,
             )
             .then(
                 ext_self::ext(current_account_id())
                     .with_unused_gas_weight(100)
                     .with_attached_deposit(NO_DEPOSIT)
-                    .remove_liquidity_for_execute_order_callback(order, order_id),
-            )
-            .into()
+                    .remove_liquidity_for_execute_order_callback(
+                        order,
+                        order_id,
+                        executor_id,
+                        position.unclaimed_fee_x,
+                        position.unclaimed_fee_y,
+                    ),
+            );
     }
 
     #[private]
@@ -80,42 +150,178 @@ impl Contract {
         &mut self,
         order: Order,
         order_id: U128,
-    ) -> PromiseOrValue<U128> {
-        if !is_promise_success() {
-            panic!("Some problem with remove liquidity");
-        } else {
-            self.mark_order_as_executed(order.clone(), order_id);
+        executor_id: AccountId,
+        unclaimed_fee_x: U128,
+        unclaimed_fee_y: U128,
+    ) {
+        let mut order = order;
+        let removed_amounts = match env::promise_result(0) {
+            PromiseResult::Successful(val) => serde_json::from_slice::<(U128, U128)>(&val).ok(),
+            _ => None,
+        };
 
-            let executor_reward_in_near = env::used_gas().0 as Balance * 2u128;
-            Promise::new(env::signer_account_id())
-                .transfer(executor_reward_in_near)
-                .into()
-        }
+        let (remaining_sell_amount, filled_buy_amount) = match removed_amounts {
+            Some((amount_x, amount_y)) => self.map_pool_amounts(&order, amount_x, amount_y),
+            None => {
+                self.revert_order(order_id, order, "Some problem with remove liquidity");
+                return;
+            }
+        };
+
+        let position_amount =
+            WBalance::from(BigDecimal::from(U128(order.amount)) * order.leverage).0;
+
+        order.remaining_sell_amount = remaining_sell_amount.0;
+        order.filled_buy_amount = filled_buy_amount.0;
+        order.filled_sell_amount = position_amount.saturating_sub(remaining_sell_amount.0);
+        order.execution_block = env::block_height();
+        order.execution_price = if filled_buy_amount.0 > 0 {
+            BigDecimal::from(U128(order.filled_sell_amount)) / BigDecimal::from(filled_buy_amount)
+        } else {
+            BigDecimal::zero()
+        };
+        // pool fees earned by the liquidity are claimed by ref finance alongside with its removal
+        let account_id = self.get_account_by(order_id.0).unwrap();
+        self.credit_pool_fees(
+            &account_id,
+            &mut order,
+            unclaimed_fee_x.0,
+            unclaimed_fee_y.0,
+        );
+        // escrowed bounty is credited to the executor ledger in its own token
+        self.record_keeper_reward(&executor_id, KeeperAction::Execute, &mut order, 0);
+        self.mark_order_as_executed(order, order_id);
     }
 }
 
 impl Contract {
     pub fn mark_order_as_executed(&mut self, order: Order, order_id: U128) {
         let order = order.clone();
+        let account_id = self.get_account_by(order_id.0).unwrap(); // assert there is always some user
+
+        self.record_trade_volume(&account_id, &order.sell_token, order.filled_sell_amount);
 
         let new_order = Order {
             status: OrderStatus::Executed,
-            order_type: order.order_type,
-            amount: order.amount,
-            sell_token: order.sell_token,
-            buy_token: order.buy_token,
-            leverage: order.leverage,
-            sell_token_price: order.sell_token_price,
-            buy_token_price: order.buy_token_price,
-            block: order.block,
-            lpt_id: order.lpt_id,
+            ..order
         };
 
-        self.insert_order_for_user(
-            &self.get_account_by(order_id.0).unwrap(), // assert there is always some user
-            new_order.clone(),
-            order_id.clone().0 as u64,
+        self.insert_order_for_user(&account_id, new_order.clone(), order_id.clone().0 as u64);
+    }
+
+    /// Updates status of the stored order, e.g. to mark it as being processed by some flow.
+    pub fn set_order_status(&mut self, order_id: U128, status: OrderStatus) {
+        let account_id = self.get_account_by(order_id.0).unwrap();
+        let mut orders = self.orders.get(&account_id).unwrap();
+        if let Some(order) = orders.get_mut(&(order_id.0 as u64)) {
+            order.status = status;
+        }
+        self.orders.insert(&account_id, &orders);
+    }
+
+    /// Restores the order, whose flow has failed, with its prior state.
+    pub fn revert_order(&mut self, order_id: U128, order: Order, reason: &str) {
+        log!(
+            "{}",
+            Events::OrderFlowFailed(
+                order_id,
+                format!("reverted to {:?} status: {}", order.status, reason)
+            )
         );
+        let account_id = self.get_account_by(order_id.0).unwrap();
+        self.insert_order_for_user(&account_id, order, order_id.0 as u64);
+    }
+
+    /// Checks whether the order is not finished and isn't processed by any other flow.
+    pub fn is_order_open(&self, order: &Order) -> bool {
+        order.status == OrderStatus::Pending || order.status == OrderStatus::Executed
+    }
+
+    /// Returns escrowed bounty of the order to its owner: NEAR is transferred,
+    /// while token bounty is credited to the owner balance.
+    pub fn refund_bounty(&mut self, order: &mut Order, receiver: &AccountId) -> Option<Promise> {
+        let bounty = order.executor_bounty;
+        order.executor_bounty = 0;
+
+        if bounty == 0 {
+            return None;
+        }
+
+        match &order.bounty_token {
+            Some(token) => {
+                self.increase_balance(receiver, token, bounty);
+                None
+            }
+            None => Some(Promise::new(receiver.clone()).transfer(bounty)),
+        }
+    }
+
+    /// Checks whether the sell token is the pool `token_x` by the pool id of the order liquidity.
+    ///
+    /// Liquidity in `token_x` is placed above the pool current point & converted as it grows,
+    /// while liquidity in `token_y` is placed below & converted as the point falls.
+    pub fn is_sell_token_x(&self, order: &Order) -> bool {
+        let pool_id = order.lpt_id.split('#').next().unwrap_or_default();
+        Self::parse_pool_id(pool_id)
+            .map(|(token_x, _, _)| token_x == order.sell_token)
+            .unwrap_or(true)
+    }
+
+    /// Maps the pool `token_x` & `token_y` amounts to the order sell & buy token ones.
+    /// Mapping is symmetric, so it maps the order amounts to the pool ones as well.
+    pub fn map_pool_amounts<T>(&self, order: &Order, first: T, second: T) -> (T, T) {
+        match self.is_sell_token_x(order) {
+            true => (first, second),
+            false => (second, first),
+        }
+    }
+
+    /// Returns the number of points of the order range crossed by the pool current point.
+    pub fn crossed_points(&self, order: &Order, current_point: i32) -> i32 {
+        let crossed_points = match self.is_sell_token_x(order) {
+            true => current_point - order.left_point,
+            false => order.right_point - current_point,
+        };
+        crossed_points.clamp(0, order.right_point - order.left_point)
+    }
+
+    /// Checks whether the market has crossed the whole range of the order liquidity,
+    /// so it is fully converted into the buy token.
+    pub fn is_order_range_crossed(&self, order: &Order, current_point: i32) -> bool {
+        self.crossed_points(order, current_point) == order.right_point - order.left_point
+    }
+
+    /// Returns minimal buy token amount expected from removing the order liquidity
+    /// at the order prices reduced by the slippage tolerance.
+    ///
+    /// Partially filled order is executed with the crossed part of the range converted
+    /// into the buy token, while the rest of the liquidity is returned in the sell token.
+    pub fn calculate_execution_floor(&self, order: &Order, current_point: i32) -> WBalance {
+        let mut expected_amount =
+            BigDecimal::from(U128(order.amount)) * order.leverage * order.sell_token_price.value
+                / order.buy_token_price.value;
+
+        if !self.is_order_range_crossed(order, current_point) {
+            expected_amount = expected_amount
+                * BigDecimal::from(self.crossed_points(order, current_point))
+                / BigDecimal::from(order.right_point - order.left_point);
+        }
+
+        self.apply_slippage_tolerance(expected_amount)
+    }
+
+    /// Returns the sell & buy token amounts the order position currently consists of.
+    ///
+    /// Pending order is fully presented by the sell token, while executed one by the amounts
+    /// actually received from ref finance.
+    pub fn position_amounts(&self, order: &Order) -> (Balance, Balance) {
+        match order.status {
+            OrderStatus::Pending => (
+                WBalance::from(BigDecimal::from(U128(order.amount)) * order.leverage).0,
+                0,
+            ),
+            _ => (order.remaining_sell_amount, order.filled_buy_amount),
+        }
     }
 
     pub fn get_account_by(&self, order_id: u128) -> Option<AccountId> {
@@ -129,3 +335,66 @@ impl Contract {
         account
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn execution_floor_is_reduced_by_slippage_tolerance_test() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .predecessor_account_id(alice())
+            .build());
+        let contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+        let order: Order = serde_json::from_str("{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#1\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}").unwrap();
+
+        // fully crossed range is expected to be converted into 250 wnear less 5% slippage
+        assert_eq!(
+            contract.calculate_execution_floor(&order, 40),
+            U128(2375 * 10_u128.pow(23))
+        );
+
+        // a quarter of the range is crossed, so a quarter of the amount is expected
+        assert_eq!(
+            contract.calculate_execution_floor(&order, 10),
+            U128(59375 * 10_u128.pow(21))
+        );
+    }
+
+    #[test]
+    fn token_y_liquidity_is_crossed_downwards_test() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .predecessor_account_id(alice())
+            .build());
+        let contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+        // wnear is token y of the pool, so its liquidity is placed below the current point
+        let order: Order = serde_json::from_str("{\"status\":\"Pending\",\"order_type\":\"Sell\",\"amount\":1000000000000000000000000000,\"sell_token\":\"wnear.qa.v1.nearlend.testnet\",\"buy_token\":\"usdt.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"buy_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#1\",\"left_point\":-40,\"right_point\":0,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}").unwrap();
+
+        assert!(!contract.is_sell_token_x(&order));
+        assert_eq!(contract.map_pool_amounts(&order, 1, 2), (2, 1));
+
+        assert_eq!(contract.crossed_points(&order, 10), 0);
+        assert_eq!(contract.crossed_points(&order, -10), 10);
+        assert!(!contract.is_order_range_crossed(&order, -10));
+        assert!(contract.is_order_range_crossed(&order, -40));
+
+        // a quarter of the range is crossed, so a quarter of 4000 usdt less 5% slippage is expected
+        assert_eq!(
+            contract.calculate_execution_floor(&order, -10),
+            U128(950 * 10_u128.pow(24))
+        );
+        // the rest of 1000 wnear is expected to be returned less 5% slippage
+        assert_eq!(
+            contract.calculate_sell_floor(&order, -10),
+            U128(7125 * 10_u128.pow(23))
+        );
+    }
+}
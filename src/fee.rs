@@ -0,0 +1,378 @@
+use crate::big_decimal::{BigDecimal, WBalance};
+use crate::*;
+
+/// Days of the rolling window trade volume is tracked for
+const VOLUME_WINDOW_DAYS: u64 = 30;
+const NANOSECONDS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl Contract {
+    pub fn view_fee_tiers(&self) -> Vec<FeeTier> {
+        self.fee_tiers.clone()
+    }
+
+    /// Returns trade volume of the account within the rolling window & the fee tier it reached.
+    pub fn view_fee_tier(&self, account_id: AccountId) -> FeeTierView {
+        let volume = self.get_trade_volume(&account_id);
+        let tier = self.get_fee_tier_index(volume);
+
+        FeeTierView {
+            account_id,
+            volume: U128(volume),
+            tier: tier.map(|index| index as u32),
+            fee_discount: tier
+                .map(|index| self.fee_tiers[index].fee_discount)
+                .unwrap_or(U128(0)),
+        }
+    }
+
+    pub fn view_pool_fee_owner_share(&self) -> U128 {
+        U128(self.pool_fee_owner_share)
+    }
+
+    pub fn view_default_fees(&self) -> FeeSchedule {
+        self.default_fees.clone()
+    }
+
+    /// Returns fee schedule applied to the pair.
+    pub fn view_pair_fees(&self, sell_token: AccountId, buy_token: AccountId) -> FeeSchedule {
+        self.get_pair_fees(&sell_token, &buy_token)
+    }
+}
+
+impl Contract {
+    /// Sets fee tiers, which have to be sorted by the minimal volume.
+    pub fn set_fee_tiers(&mut self, tiers: Vec<FeeTier>) {
+        Self::assert_fee_tiers_valid(&tiers);
+        self.fee_tiers = tiers;
+    }
+
+    pub fn set_pool_fee_owner_share(&mut self, share: U128) {
+        Self::assert_share_valid(share, "Pool fee owner share");
+        self.pool_fee_owner_share = share.0;
+    }
+
+    pub fn set_default_fees(&mut self, fees: FeeSchedule) {
+        Self::assert_fees_valid(&fees);
+        self.default_fees = fees;
+    }
+
+    /// Sets fee schedule of the supported pair, which overrides the default one.
+    pub fn set_pair_fees(
+        &mut self,
+        sell_token: AccountId,
+        buy_token: AccountId,
+        fees: FeeSchedule,
+    ) {
+        require!(
+            self.supported_markets
+                .get(&(sell_token.clone(), buy_token.clone()))
+                .is_some(),
+            "Pair is not supported"
+        );
+        Self::assert_fees_valid(&fees);
+        self.pair_fees.insert(&(sell_token, buy_token), &fees);
+    }
+
+    /// Removes own fee schedule of the pair, so the default one is applied.
+    pub fn remove_pair_fees(&mut self, sell_token: AccountId, buy_token: AccountId) {
+        self.pair_fees.remove(&(sell_token, buy_token));
+    }
+
+    pub fn get_pair_fees(&self, sell_token: &AccountId, buy_token: &AccountId) -> FeeSchedule {
+        self.pair_fees
+            .get(&(sell_token.clone(), buy_token.clone()))
+            .unwrap_or_else(|| self.default_fees.clone())
+    }
+
+    /// Returns fee schedule of the pair with opening & closing fees discounted by the account fee tier.
+    pub fn get_account_fees(
+        &self,
+        account_id: &AccountId,
+        sell_token: &AccountId,
+        buy_token: &AccountId,
+    ) -> FeeSchedule {
+        let fees = self.get_pair_fees(sell_token, buy_token);
+        let discount = match self.get_fee_tier_index(self.get_trade_volume(account_id)) {
+            Some(index) => self.fee_tiers[index].fee_discount,
+            None => return fees,
+        };
+
+        FeeSchedule {
+            opening_fee: U128(
+                fees.opening_fee.0 - Self::calculate_fee(fees.opening_fee.0, discount),
+            ),
+            closing_fee: U128(
+                fees.closing_fee.0 - Self::calculate_fee(fees.closing_fee.0, discount),
+            ),
+            ..fees
+        }
+    }
+
+    /// Adds traded token amount to the account volume of the current day.
+    /// Volume is tracked in the reference quote currency, so the trade is skipped if token price is unknown.
+    pub fn record_trade_volume(
+        &mut self,
+        account_id: &AccountId,
+        token: &AccountId,
+        amount: Balance,
+    ) {
+        let price = match self.prices.get(token) {
+            Some(price) => price.value,
+            None => return,
+        };
+        let volume = WBalance::from(BigDecimal::from(U128(amount)) * price).0;
+
+        let today = Self::current_day();
+        let mut volumes = self.account_volumes.get(account_id).unwrap_or_default();
+        volumes.retain(|(day, _)| day + VOLUME_WINDOW_DAYS > today);
+        match volumes.last_mut() {
+            Some((day, day_volume)) if *day == today => *day_volume += volume,
+            _ => volumes.push((today, volume)),
+        }
+        self.account_volumes.insert(account_id, &volumes);
+    }
+
+    /// Returns trade volume of the account within the rolling window.
+    pub fn get_trade_volume(&self, account_id: &AccountId) -> Balance {
+        let today = Self::current_day();
+        self.account_volumes
+            .get(account_id)
+            .unwrap_or_default()
+            .iter()
+            .filter(|(day, _)| day + VOLUME_WINDOW_DAYS > today)
+            .map(|(_, volume)| volume)
+            .sum()
+    }
+
+    fn get_fee_tier_index(&self, volume: Balance) -> Option<usize> {
+        self.fee_tiers
+            .iter()
+            .rposition(|tier| tier.min_volume.0 <= volume)
+    }
+
+    fn current_day() -> u64 {
+        env::block_timestamp() / NANOSECONDS_PER_DAY
+    }
+
+    /// Splits ref finance pool fees claimed on liquidity removal between the order owner & protocol.
+    ///
+    /// Fees are claimed in the pool `token_x` & `token_y`, so they are matched with the order tokens
+    /// by the pool id of the order liquidity.
+    pub fn credit_pool_fees(
+        &mut self,
+        account_id: &AccountId,
+        order: &mut Order,
+        fee_x: Balance,
+        fee_y: Balance,
+    ) {
+        let (sell_fee, buy_fee) = self.map_pool_amounts(order, fee_x, fee_y);
+
+        let owner_share = U128(self.pool_fee_owner_share);
+        let owner_sell_fee = Self::calculate_fee(sell_fee, owner_share);
+        let owner_buy_fee = Self::calculate_fee(buy_fee, owner_share);
+
+        if owner_sell_fee > 0 {
+            self.increase_balance(account_id, &order.sell_token, owner_sell_fee);
+        }
+        if owner_buy_fee > 0 {
+            self.increase_balance(account_id, &order.buy_token, owner_buy_fee);
+        }
+        self.add_protocol_profit(&order.sell_token, sell_fee - owner_sell_fee);
+        self.add_protocol_profit(&order.buy_token, buy_fee - owner_buy_fee);
+
+        order.accrued_fee_x += owner_sell_fee;
+        order.accrued_fee_y += owner_buy_fee;
+    }
+
+    /// Returns the fee part of the amount.
+    pub fn calculate_fee(amount: Balance, fee: WRatio) -> Balance {
+        WBalance::from(BigDecimal::from(U128(amount)) * BigDecimal::from(fee)).0
+    }
+
+    pub fn assert_fee_tiers_valid(tiers: &[FeeTier]) {
+        require!(
+            tiers
+                .windows(2)
+                .all(|pair| pair[0].min_volume.0 < pair[1].min_volume.0),
+            "Fee tiers should be sorted by the minimal volume"
+        );
+        require!(
+            tiers
+                .iter()
+                .all(|tier| tier.fee_discount.0 <= 10_u128.pow(24)),
+            "Fee discount should not exceed 100%"
+        );
+    }
+
+    pub fn assert_share_valid(share: U128, name: &str) {
+        require!(
+            share.0 <= 10_u128.pow(24),
+            format!("{} should not exceed 100%", name)
+        );
+    }
+
+    pub fn assert_fees_valid(fees: &FeeSchedule) {
+        let one = 10_u128.pow(24);
+        require!(
+            fees.opening_fee.0 < one
+                && fees.closing_fee.0 < one
+                && fees.profit_share_fee.0 < one
+                && fees.liquidation_fee.0 < one,
+            "Fee should be less than 100%"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::test_env::{alice, bob};
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_pair_fees_override_default_ones() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .build());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let sell_token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        let buy_token: AccountId = "wnear.qa.v1.nearlend.testnet".parse().unwrap();
+        contract.insert_pair(TradePair {
+            sell_ticker_id: "usdt".to_string(),
+            sell_token: sell_token.clone(),
+            sell_token_market: "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            buy_ticker_id: "wnear".to_string(),
+            buy_token: buy_token.clone(),
+            pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
+        });
+
+        assert_eq!(
+            contract.view_pair_fees(sell_token.clone(), buy_token.clone()),
+            FeeSchedule::default()
+        );
+
+        let fees = FeeSchedule {
+            opening_fee: U128(10_u128.pow(21)),
+            closing_fee: U128(2 * 10_u128.pow(21)),
+            profit_share_fee: U128(2 * 10_u128.pow(23)),
+            liquidation_fee: U128(10_u128.pow(22)),
+        };
+        contract.set_pair_fees(sell_token.clone(), buy_token.clone(), fees.clone());
+        assert_eq!(
+            contract.view_pair_fees(sell_token.clone(), buy_token.clone()),
+            fees
+        );
+        assert_eq!(
+            Contract::calculate_fee(10_u128.pow(27), fees.opening_fee),
+            10_u128.pow(24)
+        );
+
+        contract.remove_pair_fees(sell_token.clone(), buy_token.clone());
+        assert_eq!(
+            contract.view_pair_fees(sell_token, buy_token),
+            FeeSchedule::default()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Fee should be less than 100%")]
+    fn test_fee_can_not_exceed_amount() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .build());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        contract.set_default_fees(FeeSchedule {
+            liquidation_fee: U128(10_u128.pow(24)),
+            ..FeeSchedule::default()
+        });
+    }
+
+    #[test]
+    fn test_fee_tier_follows_rolling_volume() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .block_timestamp(0)
+            .build());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        contract.update_or_insert_price(
+            token.clone(),
+            Price {
+                ticker_id: "USDT".to_string(),
+                value: BigDecimal::from(2.0),
+            },
+        );
+        contract.set_fee_tiers(vec![
+            FeeTier {
+                min_volume: U128(10_u128.pow(27)),
+                fee_discount: U128(10_u128.pow(23)),
+            },
+            FeeTier {
+                min_volume: U128(10_u128.pow(28)),
+                fee_discount: U128(5 * 10_u128.pow(23)),
+            },
+        ]);
+
+        contract.record_trade_volume(&alice(), &token, 10_u128.pow(27));
+        let tier = contract.view_fee_tier(alice());
+        assert_eq!(tier.volume, U128(2 * 10_u128.pow(27)));
+        assert_eq!(tier.tier, Some(0));
+
+        let fees = contract.get_account_fees(&alice(), &token, &bob());
+        assert_eq!(fees.closing_fee, U128(27 * 10_u128.pow(19)));
+        assert_eq!(
+            fees.profit_share_fee,
+            FeeSchedule::default().profit_share_fee
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .block_timestamp(VOLUME_WINDOW_DAYS * NANOSECONDS_PER_DAY)
+            .build());
+        let tier = contract.view_fee_tier(alice());
+        assert_eq!(tier.volume, U128(0));
+        assert_eq!(tier.tier, None);
+    }
+
+    #[test]
+    fn test_pool_fees_are_split_between_owner_and_protocol() {
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let mut order: Order = near_sdk::serde_json::from_str("{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}").unwrap();
+
+        contract.credit_pool_fees(&alice(), &mut order, 1000, 300);
+
+        assert_eq!((order.accrued_fee_x, order.accrued_fee_y), (500, 150));
+        assert_eq!(contract.balance_of(alice(), order.sell_token.clone()), 500);
+        assert_eq!(contract.balance_of(alice(), order.buy_token.clone()), 150);
+        assert_eq!(
+            contract.view_protocol_profit(order.sell_token.clone()),
+            U128(500)
+        );
+        assert_eq!(
+            contract.view_protocol_profit(order.buy_token.clone()),
+            U128(150)
+        );
+
+        // sell token is the pool token_y, so fee_y is credited in the sell token
+        order.lpt_id =
+            "wnear.qa.v1.nearlend.testnet|usdt.qa.v1.nearlend.testnet|2000#133".to_string();
+        contract.credit_pool_fees(&alice(), &mut order, 1000, 300);
+
+        assert_eq!((order.accrued_fee_x, order.accrued_fee_y), (650, 650));
+        assert_eq!(contract.balance_of(alice(), order.sell_token.clone()), 650);
+        assert_eq!(contract.balance_of(alice(), order.buy_token.clone()), 650);
+    }
+}
@@ -9,6 +9,18 @@ impl FungibleTokenReceiver for Contract {
     /// Accepts token to be deposited by user.
     ///
     /// msg format for deposit "{"Deposit": {"token": "<token_to_be_deposited>"}}"
+    ///
+    /// msg format for deposit on behalf of another account
+    /// "{"Deposit": {"token": "<token_to_be_deposited>", "beneficiary": "<account_id>"}}"
+    ///
+    /// msg format for order creation
+    /// "{"CreateOrder": {"order_type": "Buy", "sell_token": "<transferred_token>", "buy_token": "<token>", "leverage": "1", "amount": "<optional_order_amount>", "referral_code": "<optional_code>", "executor_bounty": "<optional_bounty>"}}"
+    ///
+    /// Executor bounty of the order is taken in the transferred token.
+    ///
+    /// msg format for the order swap output sent back by ref finance "{"SwapOutput": {"order_id": "<order_id>"}}"
+    ///
+    /// Amount not used by the order is returned to the sender.
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
@@ -25,10 +37,191 @@ impl FungibleTokenReceiver for Contract {
         let action: Actions = serde_json::from_str(&msg).expect("Incorrect command in transfer");
 
         match action {
-            Actions::Deposit { token } => self.deposit(amount, token),
-            _ => {
-                panic!("Incorrect action in transfer")
+            Actions::Deposit { token, beneficiary } => {
+                require!(
+                    token == env::predecessor_account_id(),
+                    "Deposited token doesn't match the transferred one"
+                );
+                self.deposit(beneficiary.unwrap_or(sender_id), amount, token)
             }
+            Actions::CreateOrder {
+                order_type,
+                sell_token,
+                buy_token,
+                leverage,
+                amount: order_amount,
+                referral_code,
+                executor_bounty,
+            } => {
+                require!(
+                    sell_token == env::predecessor_account_id(),
+                    "Sell token doesn't match the transferred one"
+                );
+                self.assert_not_paused(PauseAction::CreateOrder, Some((&sell_token, &buy_token)));
+
+                let executor_bounty = executor_bounty.unwrap_or(U128(0));
+                let min_bounty = self.get_min_token_bounty(&sell_token);
+                require!(
+                    executor_bounty.0 >= min_bounty,
+                    format!(
+                        "Executor bounty should be at least {} of {}",
+                        min_bounty, sell_token
+                    )
+                );
+                require!(
+                    executor_bounty.0 < amount.0,
+                    "Executor bounty should be less than transferred amount"
+                );
+
+                let order_amount = order_amount.unwrap_or(U128(amount.0 - executor_bounty.0));
+                require!(
+                    order_amount.0 > 0 && order_amount.0 + executor_bounty.0 <= amount.0,
+                    "Order amount with executor bounty should be positive & not greater than transferred amount"
+                );
+
+                if self
+                    .supported_markets
+                    .get(&(sell_token.clone(), buy_token.clone()))
+                    .is_none()
+                {
+                    log!(
+                        "Pair {}/{} is not supported, transferred amount is returned",
+                        sell_token,
+                        buy_token
+                    );
+                    return PromiseOrValue::Value(amount);
+                }
+
+                if let Some(referral_code) = referral_code {
+                    self.apply_referral_code(&sender_id, referral_code);
+                }
+
+                self.increase_balance(&sender_id, &sell_token, order_amount.0);
+                // order flow continues detached, so its failure keeps tokens in user balance
+                // instead of refunding them by the token contract once again
+                let _ = self.place_order(
+                    sender_id,
+                    order_type,
+                    order_amount,
+                    sell_token,
+                    buy_token,
+                    leverage,
+                    executor_bounty.0,
+                    Some(env::predecessor_account_id()),
+                );
+
+                PromiseOrValue::Value(U128(amount.0 - order_amount.0 - executor_bounty.0))
+            }
+            Actions::SwapOutput { order_id } => {
+                require!(
+                    sender_id == self.ref_finance_account,
+                    "Swap output is expected from ref finance"
+                );
+                self.on_swap_output(order_id, env::predecessor_account_id(), amount);
+                PromiseOrValue::Value(U128(0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::big_decimal::BigDecimal;
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, VMContext};
+
+    fn get_context() -> VMContext {
+        VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .signer_account_id(alice())
+            .predecessor_account_id("usdt.qa.v1.nearlend.testnet".parse().unwrap())
+            .block_index(103930920)
+            .block_timestamp(1)
+            .build()
+    }
+
+    fn get_contract() -> Contract {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .build());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        contract.insert_pair(TradePair {
+            sell_ticker_id: "usdt".to_string(),
+            sell_token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            sell_token_market: "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            buy_ticker_id: "wnear".to_string(),
+            buy_token: "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
+        });
+        contract.set_min_token_bounty("usdt.qa.v1.nearlend.testnet".parse().unwrap(), U128(100));
+        contract.update_or_insert_price(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "USDT".to_string(),
+                value: BigDecimal::from(1.0),
+            },
+        );
+        contract.update_or_insert_price(
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "WNEAR".to_string(),
+                value: BigDecimal::from(4.22),
+            },
+        );
+        contract
+    }
+
+    fn refunded_amount(result: PromiseOrValue<U128>) -> Balance {
+        match result {
+            PromiseOrValue::Value(amount) => amount.0,
+            PromiseOrValue::Promise(_) => panic!("Expected refunded amount"),
         }
     }
+
+    #[test]
+    fn test_create_order_on_transfer_returns_unused_amount() {
+        let mut contract = get_contract();
+        testing_env!(get_context());
+
+        let msg = r#"{"CreateOrder": {"order_type": "Buy", "sell_token": "usdt.qa.v1.nearlend.testnet", "buy_token": "wnear.qa.v1.nearlend.testnet", "leverage": "1", "amount": "700", "executor_bounty": "100"}}"#;
+        let result = contract.ft_on_transfer(alice(), U128(1000), msg.to_string());
+
+        assert_eq!(refunded_amount(result), 200);
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            700
+        );
+    }
+
+    #[test]
+    fn test_create_order_on_transfer_for_unsupported_pair_is_refunded() {
+        let mut contract = get_contract();
+        testing_env!(get_context());
+
+        let msg = r#"{"CreateOrder": {"order_type": "Buy", "sell_token": "usdt.qa.v1.nearlend.testnet", "buy_token": "eth.qa.v1.nearlend.testnet", "leverage": "1", "amount": null, "executor_bounty": "100"}}"#;
+        let result = contract.ft_on_transfer(alice(), U128(1000), msg.to_string());
+
+        assert_eq!(refunded_amount(result), 1000);
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            0
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Executor bounty should be at least 100 of usdt.qa.v1.nearlend.testnet"
+    )]
+    fn test_create_order_on_transfer_with_too_low_bounty_fails() {
+        let mut contract = get_contract();
+        testing_env!(get_context());
+
+        let msg = r#"{"CreateOrder": {"order_type": "Buy", "sell_token": "usdt.qa.v1.nearlend.testnet", "buy_token": "wnear.qa.v1.nearlend.testnet", "leverage": "1", "amount": null, "executor_bounty": "1"}}"#;
+        contract.ft_on_transfer(alice(), U128(1000), msg.to_string());
+    }
 }
@@ -0,0 +1,212 @@
+use crate::utils::ext_token;
+use crate::*;
+use near_sdk::env::current_account_id;
+use near_sdk::{ext_contract, is_promise_success, log, Gas, Promise, ONE_YOCTO};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_CLAIM_CALLBACK: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_self)]
+trait ContractCallbackInterface {
+    fn claim_reward_callback(
+        &mut self,
+        account_id: AccountId,
+        token: Option<AccountId>,
+        amount: U128,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers all unclaimed executor & liquidator rewards of the caller.
+    /// Reward is returned to the ledger if its transfer fails.
+    pub fn claim_rewards(&mut self) -> Promise {
+        self.assert_not_paused(PauseAction::ClaimRewards, None);
+        let account_id = env::predecessor_account_id();
+        let mut stats = self.keeper_stats.get(&account_id).unwrap_or_default();
+
+        let mut transfers = stats
+            .unclaimed_tokens
+            .drain()
+            .filter(|(_, amount)| amount.0 > 0)
+            .map(|(token, amount)| {
+                ext_token::ext(token.clone())
+                    .with_static_gas(GAS_FOR_FT_TRANSFER)
+                    .with_attached_deposit(ONE_YOCTO)
+                    .ft_transfer(account_id.clone(), amount, None)
+                    .then(
+                        ext_self::ext(current_account_id())
+                            .with_static_gas(GAS_FOR_CLAIM_CALLBACK)
+                            .claim_reward_callback(account_id.clone(), Some(token), amount),
+                    )
+            })
+            .collect::<Vec<Promise>>();
+
+        if stats.unclaimed_near.0 > 0 {
+            transfers.push(
+                Promise::new(account_id.clone())
+                    .transfer(stats.unclaimed_near.0)
+                    .then(
+                        ext_self::ext(current_account_id())
+                            .with_static_gas(GAS_FOR_CLAIM_CALLBACK)
+                            .claim_reward_callback(account_id.clone(), None, stats.unclaimed_near),
+                    ),
+            );
+            stats.unclaimed_near = U128(0);
+        }
+
+        self.keeper_stats.insert(&account_id, &stats);
+
+        transfers
+            .into_iter()
+            .reduce(|all, transfer| all.and(transfer))
+            .unwrap_or_else(|| panic!("There are no rewards to be claimed"))
+    }
+
+    #[private]
+    pub fn claim_reward_callback(
+        &mut self,
+        account_id: AccountId,
+        token: Option<AccountId>,
+        amount: U128,
+    ) {
+        if is_promise_success() {
+            return;
+        }
+
+        log!(
+            "Failed to transfer {} of {} reward to {}",
+            amount.0,
+            token.as_ref().map(|token| token.as_str()).unwrap_or("NEAR"),
+            account_id
+        );
+
+        let mut stats = self.keeper_stats.get(&account_id).unwrap_or_default();
+        Self::add_unclaimed_reward(&mut stats, token, amount.0);
+        self.keeper_stats.insert(&account_id, &stats);
+    }
+
+    pub fn view_keeper_stats(&self, account_id: AccountId) -> KeeperStats {
+        self.keeper_stats.get(&account_id).unwrap_or_default()
+    }
+}
+
+impl Contract {
+    /// Returns the minimal executor bounty in the token, which has to be set by admin
+    /// for the token to be accepted as bounty while executor bounty is required.
+    pub fn get_min_token_bounty(&self, token: &AccountId) -> Balance {
+        if self.min_executor_bounty == 0 {
+            return 0;
+        }
+
+        self.min_token_bounties
+            .get(token)
+            .unwrap_or_else(|| panic!("Executor bounty in token {} is not supported", token))
+    }
+
+    /// Records the keeper action & credits escrowed bounty of the order alongside with
+    /// the additional reward in the sell token to the keeper ledger.
+    pub fn record_keeper_reward(
+        &mut self,
+        keeper: &AccountId,
+        action: KeeperAction,
+        order: &mut Order,
+        sell_token_reward: Balance,
+    ) {
+        let mut stats = self.keeper_stats.get(keeper).unwrap_or_default();
+        match action {
+            KeeperAction::Execute => stats.executions += 1,
+            // force close is counted alongside liquidations, as it closes someone else's position
+            KeeperAction::Liquidate | KeeperAction::ForceClose => stats.liquidations += 1,
+        }
+
+        let bounty = order.executor_bounty;
+        order.executor_bounty = 0;
+
+        Self::add_earned_reward(&mut stats, order.bounty_token.clone(), bounty);
+        Self::add_earned_reward(
+            &mut stats,
+            Some(order.sell_token.clone()),
+            sell_token_reward,
+        );
+
+        self.keeper_stats.insert(keeper, &stats);
+    }
+
+    fn add_earned_reward(stats: &mut KeeperStats, token: Option<AccountId>, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+
+        match &token {
+            Some(token) => {
+                let earned = stats.earned_tokens.get(token).map(|a| a.0).unwrap_or(0);
+                stats
+                    .earned_tokens
+                    .insert(token.clone(), U128(earned + amount));
+            }
+            None => stats.earned_near = U128(stats.earned_near.0 + amount),
+        }
+
+        Self::add_unclaimed_reward(stats, token, amount);
+    }
+
+    fn add_unclaimed_reward(stats: &mut KeeperStats, token: Option<AccountId>, amount: Balance) {
+        match token {
+            Some(token) => {
+                let unclaimed = stats.unclaimed_tokens.get(&token).map(|a| a.0).unwrap_or(0);
+                stats
+                    .unclaimed_tokens
+                    .insert(token, U128(unclaimed + amount));
+            }
+            None => stats.unclaimed_near = U128(stats.unclaimed_near.0 + amount),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{serde_json, testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    #[test]
+    fn test_keeper_rewards_are_accumulated_and_restored_on_failed_claim() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice())
+            .build());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let mut order: Order = serde_json::from_str("{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":0,\"filled_sell_amount\":0,\"execution_block\":0,\"execution_price\":\"0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":100,\"bounty_token\":null}").unwrap();
+        let token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+
+        contract.record_keeper_reward(&alice(), KeeperAction::Liquidate, &mut order, 500);
+        assert_eq!(order.executor_bounty, 0);
+
+        let stats = contract.view_keeper_stats(alice());
+        assert_eq!((stats.executions, stats.liquidations), (0, 1));
+        assert_eq!(stats.earned_near, U128(100));
+        assert_eq!(stats.unclaimed_tokens.get(&token), Some(&U128(500)));
+
+        let _ = contract.claim_rewards();
+        let stats = contract.view_keeper_stats(alice());
+        assert_eq!(stats.unclaimed_near, U128(0));
+        assert!(stats.unclaimed_tokens.is_empty());
+
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.claim_reward_callback(alice(), Some(token.clone()), U128(500));
+
+        let stats = contract.view_keeper_stats(alice());
+        assert_eq!(stats.unclaimed_tokens.get(&token), Some(&U128(500)));
+        assert_eq!(stats.earned_tokens.get(&token), Some(&U128(500)));
+    }
+}
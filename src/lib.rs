@@ -1,18 +1,29 @@
 extern crate core;
 
+mod access_control;
 mod big_decimal;
 mod cancel_order;
 mod config;
 mod create_order;
+mod delisting;
 mod deposit;
+mod events;
 mod execute_order;
+mod fee;
 mod ft;
+mod keeper;
 mod liquidate_order;
 mod market;
 mod metadata;
+mod migration;
 mod oraclehook;
+mod pause;
 mod price;
+mod recovery;
 mod ref_finance;
+mod referral;
+mod timelock;
+mod treasury;
 mod utils;
 mod view;
 
@@ -25,14 +36,50 @@ use near_sdk::json_types::U128;
 use near_sdk::{env, near_bindgen, require, AccountId, Balance, PromiseOrValue};
 use std::collections::HashMap;
 
+/// Ref finance DCL account set on the initialization by default
+const DEFAULT_REF_FINANCE_ACCOUNT: &str = "dcl.ref-dev.testnet";
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct Contract {
     /// market ➝ MarketData
     market_infos: LookupMap<AccountId, MarketData>,
 
-    /// Protocol fee
-    protocol_fee: u128,
+    /// Fee schedule applied to the pairs without own one
+    default_fees: FeeSchedule,
+
+    /// (AccountId, AccountId) ➝ FeeSchedule
+    pair_fees: LookupMap<(AccountId, AccountId), FeeSchedule>,
+
+    /// Fee tiers sorted by the minimal volume
+    fee_tiers: Vec<FeeTier>,
+
+    /// user ➝ day ➝ trade volume in the reference quote currency
+    account_volumes: LookupMap<AccountId, Vec<(u64, Balance)>>,
+
+    /// Part of ref finance pool fees earned by the order liquidity credited to the order owner
+    pool_fee_owner_share: u128,
+
+    /// Minimal NEAR bounty to be attached on order creation for its executor
+    min_executor_bounty: Balance,
+
+    /// token ➝ minimal bounty in the token taken on order creation for its executor
+    min_token_bounties: LookupMap<AccountId, Balance>,
+
+    /// executor or liquidator ➝ KeeperStats
+    keeper_stats: LookupMap<AccountId, KeeperStats>,
+
+    /// Part of opening & closing fees credited to the referrer
+    referral_share: u128,
+
+    /// referral code ➝ referrer
+    referral_codes: LookupMap<String, AccountId>,
+
+    /// user ➝ referrer
+    referrers: LookupMap<AccountId, AccountId>,
+
+    /// referrer ➝ ReferralStats
+    referral_stats: LookupMap<AccountId, ReferralStats>,
 
     /// token ➝ Price
     prices: UnorderedMap<AccountId, Price>,
@@ -51,11 +98,29 @@ pub struct Contract {
 
     config: Config,
 
+    /// Account proposed as the new owner, which has to accept the ownership
+    pending_owner: Option<AccountId>,
+
+    /// account ➝ granted roles
+    roles: UnorderedMap<AccountId, Vec<Role>>,
+
+    /// Whether all the risk increasing actions are paused
+    paused: bool,
+
+    /// Pairs with paused order creation & execution
+    paused_pairs: Vec<(AccountId, AccountId)>,
+
+    /// Actions paused for all the pairs
+    paused_actions: Vec<PauseAction>,
+
     /// token id -> market id
     tokens_markets: LookupMap<AccountId, AccountId>,
 
     /// Protocol profit token_id -> amount
-    protocol_profit: LookupMap<AccountId, BigDecimal>,
+    protocol_profit: UnorderedMap<AccountId, Balance>,
+
+    /// Account protocol profit is claimed to
+    treasury_account: AccountId,
 
     /// Ref finance accountId [ as default "dcl.ref-dev.testnet" ]
     ref_finance_account: AccountId,
@@ -65,6 +130,30 @@ pub struct Contract {
 
     /// Volatility rate
     volatility_rate: BigDecimal,
+
+    /// Slippage tolerance for the swaps with oracle derived minimal output
+    slippage_tolerance: u128,
+
+    /// total operations recorded on contract
+    operation_nonce: u64,
+
+    /// operation_id ➝ PendingOperation with external effect to be recovered on failure
+    pending_operations: UnorderedMap<u64, PendingOperation>,
+
+    /// Minimal delay in nanoseconds between the parameter change proposal & its application
+    timelock_delay: u64,
+
+    /// total parameter change proposals created on contract
+    proposal_nonce: u64,
+
+    /// proposal_id ➝ ParameterProposal
+    proposals: UnorderedMap<u64, ParameterProposal>,
+
+    /// (AccountId, AccountId) ➝ PairDelisting
+    pair_delistings: UnorderedMap<(AccountId, AccountId), PairDelisting>,
+
+    /// order_id ➝ PendingSwap awaiting its output from ref finance
+    pending_swaps: LookupMap<u64, PendingSwap>,
 }
 
 impl Default for Contract {
@@ -84,50 +173,155 @@ impl Contract {
         })
     }
 
+    /// Initializes the contract with the given external accounts, so it could be deployed
+    /// to any network. Token markets are set as (token, market) pairs.
+    #[init]
+    pub fn new_with_accounts(
+        owner_id: AccountId,
+        oracle_account_id: AccountId,
+        ref_finance_account: AccountId,
+        token_markets: Vec<(AccountId, AccountId)>,
+    ) -> Self {
+        Self::assert_external_account(&oracle_account_id);
+        Self::assert_external_account(&ref_finance_account);
+
+        let mut contract = Self::new(Config {
+            owner_id,
+            oracle_account_id,
+        });
+        contract.ref_finance_account = ref_finance_account;
+        for (token_id, market_id) in token_markets {
+            contract.insert_token_market(token_id, market_id);
+        }
+        contract
+    }
+
     #[init]
     #[private]
     pub fn new(config: Config) -> Self {
         require!(!env::state_exists(), "Already initialized");
+        Self::init_state(config)
+    }
+
+    pub fn add_market_data(&mut self, market: AccountId, data: MarketData) {
+        self.assert_role(Role::PriceFeeder);
+        self.market_infos.insert(&market, &data);
+    }
+
+    pub fn add_token_market(&mut self, token_id: AccountId, market_id: AccountId) {
+        self.assert_role(Role::Admin);
+        require!(
+            !self.is_token_used_by_pairs(&token_id),
+            "Market of the token used by the supported pair can't be changed"
+        );
+        self.insert_token_market(token_id, market_id);
+    }
+
+    pub fn remove_token_market(&mut self, token_id: AccountId) {
+        self.assert_role(Role::Admin);
+        require!(
+            !self.is_token_used_by_pairs(&token_id),
+            "Market of the token used by the supported pair can't be removed"
+        );
+        self.tokens_markets.remove(&token_id);
+    }
+}
+
+impl Contract {
+    /// Returns the initial contract state with the default parameters.
+    pub fn init_state(config: Config) -> Self {
+        let treasury_account = config.owner_id.clone();
 
         Self {
             market_infos: LookupMap::new(StorageKeys::Markets),
-            protocol_fee: 10u128.pow(23),
+            default_fees: FeeSchedule::default(),
+            pair_fees: LookupMap::new(StorageKeys::PairFees),
+            fee_tiers: Vec::new(),
+            account_volumes: LookupMap::new(StorageKeys::AccountVolumes),
+            pool_fee_owner_share: 5 * 10_u128.pow(23),
+            min_executor_bounty: 10_u128.pow(22),
+            min_token_bounties: LookupMap::new(StorageKeys::MinTokenBounties),
+            keeper_stats: LookupMap::new(StorageKeys::KeeperStats),
+            referral_share: 10_u128.pow(23),
+            referral_codes: LookupMap::new(StorageKeys::ReferralCodes),
+            referrers: LookupMap::new(StorageKeys::Referrers),
+            referral_stats: LookupMap::new(StorageKeys::ReferralStats),
             prices: UnorderedMap::new(StorageKeys::Prices),
             order_nonce: 0,
             orders: UnorderedMap::new(StorageKeys::Orders),
             supported_markets: UnorderedMap::new(StorageKeys::SupportedMarkets),
             config,
+            pending_owner: None,
+            roles: UnorderedMap::new(StorageKeys::Roles),
+            paused: false,
+            paused_pairs: Vec::new(),
+            paused_actions: Vec::new(),
             balances: UnorderedMap::new(StorageKeys::Balances),
             tokens_markets: LookupMap::new(StorageKeys::TokenMarkets),
-            protocol_profit: LookupMap::new(StorageKeys::ProtocolProfit),
-            ref_finance_account: "dcl.ref-dev.testnet".parse().unwrap(),
+            protocol_profit: UnorderedMap::new(StorageKeys::ProtocolProfit),
+            treasury_account,
+            ref_finance_account: DEFAULT_REF_FINANCE_ACCOUNT.parse().unwrap(),
             liquidation_threshold: 10_u128.pow(23),
             volatility_rate: BigDecimal::from(U128(95 * 10_u128.pow(22))),
+            slippage_tolerance: 5 * 10_u128.pow(22),
+            operation_nonce: 0,
+            pending_operations: UnorderedMap::new(StorageKeys::PendingOperations),
+            timelock_delay: timelock::DEFAULT_TIMELOCK_DELAY,
+            proposal_nonce: 0,
+            proposals: UnorderedMap::new(StorageKeys::Proposals),
+            pair_delistings: UnorderedMap::new(StorageKeys::PairDelistings),
+            pending_swaps: LookupMap::new(StorageKeys::PendingSwaps),
         }
     }
 
-    #[private]
-    pub fn add_market_data(&mut self, market: AccountId, data: MarketData) {
-        self.market_infos.insert(&market, &data);
+    pub fn is_token_used_by_pairs(&self, token_id: &AccountId) -> bool {
+        self.supported_markets
+            .keys()
+            .any(|pair| &pair.0 == token_id || &pair.1 == token_id)
     }
 
-    #[private]
-    pub fn set_protocol_fee(&mut self, fee: U128) {
-        self.protocol_fee = fee.0
+    pub fn insert_token_market(&mut self, token_id: AccountId, market_id: AccountId) {
+        Self::assert_external_account(&market_id);
+        require!(
+            token_id != market_id,
+            "Token & its market should be different accounts"
+        );
+        self.tokens_markets.insert(&token_id, &market_id);
     }
 
-    #[private]
-    pub fn add_token_market(&mut self, token_id: AccountId, market_id: AccountId) {
-        self.tokens_markets.insert(&token_id, &market_id);
+    /// Checks the account could be used as external contract the protocol interacts with.
+    pub fn assert_external_account(account_id: &AccountId) {
+        require!(
+            *account_id != env::current_account_id(),
+            "External account can't be the contract itself"
+        );
     }
 
-    #[private]
     pub fn set_liquidation_threshold(&mut self, threshold: U128) {
         self.liquidation_threshold = threshold.0;
     }
 
-    #[private]
     pub fn set_volatility_rate(&mut self, rate: U128) {
         self.volatility_rate = BigDecimal::from(rate)
     }
+
+    pub fn set_slippage_tolerance(&mut self, tolerance: U128) {
+        Self::assert_slippage_tolerance_valid(tolerance);
+        self.slippage_tolerance = tolerance.0;
+    }
+
+    pub fn assert_slippage_tolerance_valid(tolerance: U128) {
+        require!(
+            tolerance.0 < 10_u128.pow(24),
+            "Slippage tolerance should be less than 100%"
+        );
+    }
+
+    pub fn set_min_executor_bounty(&mut self, bounty: U128) {
+        self.min_executor_bounty = bounty.0;
+    }
+
+    pub fn set_min_token_bounty(&mut self, token: AccountId, bounty: U128) {
+        self.min_token_bounties.insert(&token, &bounty.0);
+    }
 }
@@ -1,16 +1,15 @@
-use crate::big_decimal::{BigDecimal, WRatio};
+use crate::big_decimal::{BigDecimal, WBalance};
 use crate::cancel_order::ext_self;
-use crate::ref_finance::ext_ref_finance;
-use crate::ref_finance::{Action, Swap};
+use crate::ref_finance::{ext_ref_finance, LiquidityInfo};
+use crate::utils::ext_market;
 use crate::utils::NO_DEPOSIT;
-use crate::utils::{ext_market, ext_token};
 use crate::*;
-use near_sdk::env::{block_height, current_account_id, signer_account_id};
-use near_sdk::{ext_contract, is_promise_success, Gas, PromiseResult};
+use near_sdk::env::current_account_id;
+use near_sdk::{Gas, PromiseResult};
 
 #[near_bindgen]
 impl Contract {
-    pub fn liquidate_order(&mut self, order_id: U128, swap_fee: U128, price_impact: U128) {
+    pub fn liquidate_order(&mut self, order_id: U128) {
         let account_op = self.get_account_by(order_id.0);
         require!(
             account_op.is_some(),
@@ -29,78 +28,588 @@ impl Contract {
             })
             .clone();
 
-        require!(
-            order.status != OrderStatus::Canceled && order.status != OrderStatus::Executed,
-            "Order can't be liquidate."
+        require!(self.is_order_open(&order), "Order can't be liquidate.");
+        self.assert_not_paused(
+            PauseAction::Liquidate,
+            Some((&order.sell_token, &order.buy_token)),
         );
 
-        //TODO: set real min_amount_x/min_amount_y
-        let amount = 1;
-        let min_amount_x = order.amount;
-        let min_amount_y = 0;
+        let market_data = self.view_market_data(self.get_market_by(&order.sell_token));
+        let (is_liquidation_possible, _) = self.calculate_liquidation(&order, &market_data);
+        require!(is_liquidation_possible, "This order can't be liquidated");
+
+        self.set_order_status(order_id, OrderStatus::Liquidating);
+        let liquidator = env::predecessor_account_id();
+
+        ext_market::ext(self.get_market_by(&order.sell_token))
+            .with_static_gas(Gas::ONE_TERA * 5u64)
+            .with_attached_deposit(NO_DEPOSIT)
+            .view_market_data()
+            .then(
+                ext_self::ext(current_account_id())
+                    .with_unused_gas_weight(100)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .liquidate_market_data_callback(order_id, order, liquidator),
+            );
+    }
+
+    /// Checks the order is still liquidatable with the actual market data
+    /// before its position is touched, so the order is reverted if it isn't.
+    #[private]
+    pub fn liquidate_market_data_callback(
+        &mut self,
+        order_id: U128,
+        order: Order,
+        liquidator: AccountId,
+    ) {
+        let market_data = match env::promise_result(0) {
+            PromiseResult::Successful(val) => {
+                near_sdk::serde_json::from_slice::<MarketData>(&val).ok()
+            }
+            _ => None,
+        };
+
+        let market_data = match market_data {
+            Some(market_data) => market_data,
+            None => {
+                self.revert_order(order_id, order, "Failed to get market data");
+                return;
+            }
+        };
+
+        let (is_liquidation_possible, _) = self.calculate_liquidation(&order, &market_data);
+        if !is_liquidation_possible {
+            self.revert_order(order_id, order, "This order can't be liquidated");
+            return;
+        }
 
         if order.status == OrderStatus::Pending {
             ext_ref_finance::ext(self.ref_finance_account.clone())
-                .with_static_gas(Gas(10))
-                .with_attached_deposit(1)
-                .remove_liquidity(
-                    order.lpt_id.clone(),
-                    U128(amount),
-                    U128(min_amount_x),
-                    U128(min_amount_y),
+                .with_static_gas(Gas::ONE_TERA * 5u64)
+                .with_attached_deposit(NO_DEPOSIT)
+                .get_liquidity(order.lpt_id.clone())
+                .and(
+                    ext_ref_finance::ext(self.ref_finance_account.clone())
+                        .with_static_gas(Gas::ONE_TERA * 5u64)
+                        .with_attached_deposit(NO_DEPOSIT)
+                        .get_pool(self.view_pair(&order.sell_token, &order.buy_token).pool_id),
                 )
                 .then(
                     ext_self::ext(current_account_id())
-                        .with_static_gas(Gas(5))
+                        .with_unused_gas_weight(100)
                         .with_attached_deposit(NO_DEPOSIT)
-                        .remove_liquidity_callback(
-                            order_id,
-                            order,
-                            swap_fee,
-                            price_impact,
-                            OrderAction::Liquidate,
-                        ),
+                        .liquidate_liquidity_callback(order_id, order, liquidator, market_data),
                 );
         } else {
+            let min_out = self.calculate_swap_floor(&order);
             self.swap(
                 order_id,
                 order,
-                swap_fee,
-                price_impact,
                 OrderAction::Liquidate,
+                liquidator,
+                market_data,
+                min_out,
             );
         }
     }
 
+    /// Removes the whole liquidity of the pending order alongside with the pool fees it earned.
+    /// Removed amounts are expected to be not less than the order position split by the pool
+    /// current point reduced by the slippage tolerance.
     #[private]
-    pub fn final_liquidate(&mut self, order_id: U128, mut order: Order, market_data: MarketData) {
-        let borrow_fee = BigDecimal::from(
-            market_data.borrow_rate_ratio.0 * (block_height() - order.block) as u128,
+    pub fn liquidate_liquidity_callback(
+        &mut self,
+        order_id: U128,
+        order: Order,
+        liquidator: AccountId,
+        market_data: MarketData,
+    ) {
+        require!(
+            env::promise_results_count() == 2,
+            "Contract expected 2 results on the callback"
         );
 
-        let buy_token_amount =
-            BigDecimal::from(order.amount) * order.sell_token_price.value * order.leverage
-                / order.buy_token_price.value;
-        let loss = borrow_fee + buy_token_amount * order.buy_token_price.value
-            - BigDecimal::from(order.amount);
+        let liquidity = match env::promise_result(0) {
+            PromiseResult::Successful(val) => {
+                near_sdk::serde_json::from_slice::<LiquidityInfo>(&val).ok()
+            }
+            _ => None,
+        };
+        let pool_info = match env::promise_result(1) {
+            PromiseResult::Successful(val) => {
+                near_sdk::serde_json::from_slice::<PoolInfo>(&val).ok()
+            }
+            _ => None,
+        };
 
-        let is_liquidation_possible = loss
-            >= BigDecimal::from(order.amount)
-                * order.buy_token_price.value
-                * BigDecimal::from(10_u128.pow(24) - self.liquidation_threshold);
+        let (liquidity, pool_info) = match (liquidity, pool_info) {
+            (Some(liquidity), Some(pool_info)) => (liquidity, pool_info),
+            _ => {
+                self.revert_order(
+                    order_id,
+                    order,
+                    "Some problem with liquidity on ref finance",
+                );
+                return;
+            }
+        };
 
-        require!(is_liquidation_possible, "This order can't be liquidated");
+        // range of the order migrated from the previous layout isn't known, so ref finance one is used
+        let mut range_order = order.clone();
+        if range_order.left_point == range_order.right_point {
+            range_order.left_point = liquidity.left_point;
+            range_order.right_point = liquidity.right_point;
+        }
 
-        let liquidation_incentive = order.amount * self.liquidation_threshold;
-        self.increase_balance(
-            &env::signer_account_id(),
-            &order.buy_token.clone(),
-            liquidation_incentive,
+        let current_point = pool_info.current_point as i32;
+        let (min_amount_x, min_amount_y) = self.map_pool_amounts(
+            &range_order,
+            self.calculate_sell_floor(&range_order, current_point),
+            self.calculate_execution_floor(&range_order, current_point),
         );
+
+        ext_ref_finance::ext(self.ref_finance_account.clone())
+            .with_unused_gas_weight(50)
+            .with_attached_deposit(NO_DEPOSIT)
+            .remove_liquidity(
+                order.lpt_id.clone(),
+                liquidity.amount,
+                min_amount_x,
+                min_amount_y,
+            )
+            .then(
+                ext_self::ext(current_account_id())
+                    .with_unused_gas_weight(50)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .remove_liquidity_callback(
+                        order_id,
+                        order,
+                        liquidator,
+                        market_data,
+                        liquidity.unclaimed_fee_x,
+                        liquidity.unclaimed_fee_y,
+                    ),
+            );
+    }
+}
+
+impl Contract {
+    /// Repays the order debt from the swap output & the not filled part of the order,
+    /// rewards the liquidator and credits the rest reduced by the liquidation fee to the order owner.
+    ///
+    /// Liquidation is checked before the position is closed, so it's settled even if prices moved since.
+    pub fn final_liquidate(
+        &mut self,
+        order_id: U128,
+        order: Order,
+        liquidator: AccountId,
+        market_data: MarketData,
+        swap_output: U128,
+    ) {
+        let mut order = order;
+        let (_, liquidator_reward) = self.calculate_liquidation(&order, &market_data);
+
         let account = self.get_account_by(order_id.0).unwrap();
-        let mut orders = self.orders.get(&account).unwrap();
+        let total_amount = swap_output.0 + order.remaining_sell_amount;
+        let debt = self.calculate_debt(&order, &market_data).min(total_amount);
+        self.record_trade_volume(&account, &order.sell_token, swap_output.0);
+
+        let mut return_amount = total_amount - debt;
+        let liquidator_reward = liquidator_reward.min(return_amount);
+        return_amount -= liquidator_reward;
+
+        let fees = self.get_pair_fees(&order.sell_token, &order.buy_token);
+        let liquidation_fee =
+            Self::calculate_fee(order.amount, fees.liquidation_fee).min(return_amount);
+        return_amount -= liquidation_fee;
+
+        self.add_protocol_profit(&order.sell_token, liquidation_fee);
+        self.record_keeper_reward(
+            &liquidator,
+            KeeperAction::Liquidate,
+            &mut order,
+            liquidator_reward,
+        );
+
+        if debt > 0 {
+            self.repay(&account, &order, debt);
+        }
+        self.increase_balance(&account, &order.sell_token, return_amount);
+
         order.status = OrderStatus::Liquidated;
-        orders.insert(order_id.0 as u64, order);
-        self.orders.insert(&signer_account_id(), &orders);
+        self.insert_order_for_user(&account, order, order_id.0 as u64);
+    }
+
+    /// Returns minimal sell token amount expected from swapping the filled buy token amount
+    /// at oracle prices reduced by the slippage tolerance.
+    pub fn calculate_swap_floor(&self, order: &Order) -> WBalance {
+        let expected_amount = BigDecimal::from(U128(order.filled_buy_amount))
+            * self.calculate_xrate(order.buy_token.clone(), order.sell_token.clone());
+
+        self.apply_slippage_tolerance(expected_amount)
+    }
+
+    /// Returns minimal sell token amount expected from removing the order liquidity:
+    /// the part of the position not crossed by the pool current point reduced by the slippage tolerance.
+    pub fn calculate_sell_floor(&self, order: &Order, current_point: i32) -> WBalance {
+        if self.is_order_range_crossed(order, current_point) {
+            return U128(0);
+        }
+
+        let range_points = order.right_point - order.left_point;
+        let expected_amount = BigDecimal::from(U128(order.amount))
+            * order.leverage
+            * BigDecimal::from(range_points - self.crossed_points(order, current_point))
+            / BigDecimal::from(range_points);
+
+        self.apply_slippage_tolerance(expected_amount)
+    }
+
+    pub fn apply_slippage_tolerance(&self, amount: BigDecimal) -> WBalance {
+        WBalance::from(
+            amount * (BigDecimal::one() - BigDecimal::from(U128(self.slippage_tolerance))),
+        )
+    }
+
+    /// Checks whether the order position is below the liquidation threshold at current prices.
+    ///
+    /// The loss is `collateral + debt - position value`, where the debt includes the borrow fee
+    /// accrued since the order creation and the position is valued in sell token at oracle prices.
+    /// Position consists of the actual order amounts: not filled sell token & filled buy token,
+    /// so the pending order holding sell token only isn't affected by the buy token price.
+    /// The order is liquidatable once the loss reaches `collateral * (1 - liquidation_threshold)`.
+    ///
+    /// Returns the check result alongside the liquidator reward of `collateral * liquidation_threshold`,
+    /// which is paid in sell token as the position is closed into it.
+    pub fn calculate_liquidation(
+        &self,
+        order: &Order,
+        market_data: &MarketData,
+    ) -> (bool, Balance) {
+        let collateral = BigDecimal::from(U128(order.amount));
+        let threshold = BigDecimal::from(U128(self.liquidation_threshold));
+
+        let debt = BigDecimal::from(U128(self.calculate_debt(order, market_data)));
+
+        let (sell_token_amount, buy_token_amount) = self.position_amounts(order);
+        let position_value = BigDecimal::from(U128(sell_token_amount))
+            + BigDecimal::from(U128(buy_token_amount))
+                * self.calculate_xrate(order.buy_token.clone(), order.sell_token.clone());
+
+        let loss = if collateral + debt > position_value {
+            collateral + debt - position_value
+        } else {
+            BigDecimal::zero()
+        };
+
+        let is_liquidation_possible = loss >= collateral * (BigDecimal::one() - threshold);
+        let liquidator_reward = WBalance::from(collateral * threshold).0;
+
+        (is_liquidation_possible, liquidator_reward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, VMContext};
+
+    fn get_context() -> VMContext {
+        VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .signer_account_id(alice())
+            .predecessor_account_id(alice())
+            .block_index(103930920)
+            .block_timestamp(1)
+            .build()
+    }
+
+    fn get_contract(wnear_price: f64) -> Contract {
+        let mut contract =
+            Contract::new_with_config(alice(), "oracle_account_id.testnet".parse().unwrap());
+        contract.add_token_market(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+
+        contract.update_or_insert_price(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "USDT".to_string(),
+                value: BigDecimal::from(1.0),
+            },
+        );
+        contract.update_or_insert_price(
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "WNEAR".to_string(),
+                value: BigDecimal::from(wnear_price),
+            },
+        );
+
+        let order = "{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"2.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"1.0\"},\"block\":103930920,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#1\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":2000000000000000000000000000,\"filled_sell_amount\":2000000000000000000000000000,\"execution_block\":103930920,\"execution_price\":\"1.0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
+        contract.add_order(alice(), order);
+        contract
+    }
+
+    fn get_order(contract: &Contract) -> Order {
+        contract
+            .orders
+            .get(&alice())
+            .unwrap()
+            .get(&1)
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn calculate_liquidation_test() {
+        testing_env!(get_context());
+
+        // loss of 400 is below 90% of the collateral
+        let contract = get_contract(0.6);
+        let order = get_order(&contract);
+        let (is_liquidation_possible, _) =
+            contract.calculate_liquidation(&order, &MarketData::default());
+        assert!(!is_liquidation_possible);
+
+        // loss of 1000 reaches 90% of the collateral, reward is 10% of the collateral
+        let mut contract = contract;
+        contract.update_or_insert_price(
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "WNEAR".to_string(),
+                value: BigDecimal::from(0.5),
+            },
+        );
+        let (is_liquidation_possible, liquidator_reward) =
+            contract.calculate_liquidation(&order, &MarketData::default());
+        assert!(is_liquidation_possible);
+        assert_eq!(liquidator_reward, 10_u128.pow(26));
+    }
+
+    #[test]
+    fn pending_order_is_not_liquidated_on_buy_token_price_drop_test() {
+        testing_env!(get_context());
+        let contract = get_contract(0.1);
+        let mut order = get_order(&contract);
+        order.status = OrderStatus::Pending;
+        order.filled_buy_amount = 0;
+        order.filled_sell_amount = 0;
+
+        // pending order holds the whole position of 2000 in sell token
+        let (is_liquidation_possible, _) =
+            contract.calculate_liquidation(&order, &MarketData::default());
+        assert!(!is_liquidation_possible);
+    }
+
+    #[test]
+    fn partly_filled_order_is_valued_by_filled_amounts_test() {
+        testing_env!(get_context());
+        let mut contract = get_contract(0.5);
+        let mut order = get_order(&contract);
+        order.remaining_sell_amount = 10_u128.pow(27);
+        order.filled_buy_amount = 10_u128.pow(27);
+        order.filled_sell_amount = 10_u128.pow(27);
+
+        // position of 1000 sell & 1000 buy token is worth 1500, so the loss of 500 is below 90%
+        let (is_liquidation_possible, _) =
+            contract.calculate_liquidation(&order, &MarketData::default());
+        assert!(!is_liquidation_possible);
+
+        // position is worth 1050, so the loss of 950 exceeds 90% of the collateral
+        contract.update_or_insert_price(
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "WNEAR".to_string(),
+                value: BigDecimal::from(0.05),
+            },
+        );
+        let (is_liquidation_possible, _) =
+            contract.calculate_liquidation(&order, &MarketData::default());
+        assert!(is_liquidation_possible);
+    }
+
+    #[test]
+    fn liquidation_accounts_accrued_borrow_fee_test() {
+        testing_env!(get_context());
+        let contract = get_contract(0.6);
+        let order = get_order(&contract);
+
+        // 10% borrow fee per block for 5 blocks doubles the debt
+        let market_data = MarketData {
+            borrow_rate_ratio: U128(10_u128.pow(23)),
+            ..MarketData::default()
+        };
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .predecessor_account_id(alice())
+            .block_index(103930925)
+            .build());
+
+        let (is_liquidation_possible, _) = contract.calculate_liquidation(&order, &market_data);
+        assert!(is_liquidation_possible);
+    }
+
+    #[test]
+    fn final_liquidate_rewards_liquidator_in_sell_token_test() {
+        testing_env!(get_context());
+        let mut contract = get_contract(0.5);
+        let order = get_order(&contract);
+        let liquidator: AccountId = "liquidator.testnet".parse().unwrap();
+
+        // swap output covers the debt of 1000, reward of 100 & leaves 50 to the owner
+        contract.final_liquidate(
+            U128(1),
+            order,
+            liquidator.clone(),
+            MarketData::default(),
+            U128(1150 * 10_u128.pow(24)),
+        );
+
+        assert_eq!(get_order(&contract).status, OrderStatus::Liquidated);
+        let stats: KeeperStats = contract.view_keeper_stats(liquidator);
+        assert_eq!(stats.liquidations, 1);
+        assert_eq!(
+            stats
+                .earned_tokens
+                .get(&"usdt.qa.v1.nearlend.testnet".parse::<AccountId>().unwrap()),
+            Some(&U128(10_u128.pow(26)))
+        );
+        assert!(!stats
+            .earned_tokens
+            .contains_key(&"wnear.qa.v1.nearlend.testnet".parse::<AccountId>().unwrap()));
+
+        let repay = contract.pending_operations.values().next().unwrap();
+        assert_eq!(repay.kind, OperationKind::Repay);
+        assert_eq!(repay.amount, U128(10_u128.pow(27)));
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            50 * 10_u128.pow(24)
+        );
+    }
+
+    #[test]
+    fn final_liquidate_repays_debt_first_test() {
+        testing_env!(get_context());
+        let mut contract = get_contract(0.5);
+        let order = get_order(&contract);
+        let liquidator: AccountId = "liquidator.testnet".parse().unwrap();
+
+        // swap output doesn't cover the debt, so everything is repaid & nothing is left
+        contract.final_liquidate(
+            U128(1),
+            order,
+            liquidator.clone(),
+            MarketData::default(),
+            U128(900 * 10_u128.pow(24)),
+        );
+
+        let repay = contract.pending_operations.values().next().unwrap();
+        assert_eq!(repay.amount, U128(900 * 10_u128.pow(24)));
+        assert!(contract
+            .view_keeper_stats(liquidator)
+            .earned_tokens
+            .is_empty());
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            0
+        );
+    }
+
+    #[test]
+    fn healthy_order_is_reverted_before_position_is_closed_test() {
+        testing_env!(get_context());
+        let mut contract = get_contract(1.0);
+        let order = get_order(&contract);
+        contract.set_order_status(U128(1), OrderStatus::Liquidating);
+
+        testing_env!(
+            get_context(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                near_sdk::serde_json::to_vec(&MarketData::default()).unwrap()
+            )],
+        );
+        contract.liquidate_market_data_callback(
+            U128(1),
+            order,
+            "liquidator.testnet".parse().unwrap(),
+        );
+
+        assert!(contract.pending_swaps.get(&1).is_none());
+        assert_eq!(get_order(&contract).status, OrderStatus::Executed);
+    }
+
+    #[test]
+    fn remove_liquidity_floor_is_split_by_current_point_test() {
+        testing_env!(get_context());
+        let contract = get_contract(1.0);
+        let mut order = get_order(&contract);
+        order.status = OrderStatus::Pending;
+        order.buy_token_price.value = BigDecimal::from(2.0);
+
+        // range isn't crossed, so the whole position of 2000 is expected in sell token less 5%
+        assert_eq!(
+            contract.calculate_sell_floor(&order, -10),
+            U128(1900 * 10_u128.pow(24))
+        );
+        assert_eq!(contract.calculate_execution_floor(&order, -10), U128(0));
+
+        // a quarter of the range is crossed into 250 buy tokens
+        assert_eq!(
+            contract.calculate_sell_floor(&order, 10),
+            U128(1425 * 10_u128.pow(24))
+        );
+        assert_eq!(
+            contract.calculate_execution_floor(&order, 10),
+            U128(2375 * 10_u128.pow(23))
+        );
+    }
+
+    #[test]
+    fn pending_order_liquidation_is_reverted_without_pool_info_test() {
+        testing_env!(get_context());
+        let mut contract = get_contract(1.0);
+        let mut order = get_order(&contract);
+        order.status = OrderStatus::Pending;
+        contract.set_order_status(U128(1), OrderStatus::Liquidating);
+
+        testing_env!(
+            get_context(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(
+                    near_sdk::serde_json::to_vec(&LiquidityInfo {
+                        lpt_id: order.lpt_id.clone(),
+                        owner_id: "margin.nearland.testnet".parse().unwrap(),
+                        pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000"
+                            .to_string(),
+                        left_point: 0,
+                        right_point: 40,
+                        amount: U128(1),
+                        unclaimed_fee_x: U128(0),
+                        unclaimed_fee_y: U128(0),
+                    })
+                    .unwrap()
+                ),
+                PromiseResult::Failed,
+            ],
+        );
+        contract.liquidate_liquidity_callback(
+            U128(1),
+            order,
+            "liquidator.testnet".parse().unwrap(),
+            MarketData::default(),
+        );
+
+        assert_eq!(get_order(&contract).status, OrderStatus::Pending);
     }
 }
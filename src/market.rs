@@ -1,16 +1,282 @@
+use crate::events::Events;
+use crate::ref_finance::ext_ref_finance;
+use crate::utils::{ext_market, NO_DEPOSIT};
 use crate::*;
+use near_sdk::env::current_account_id;
+use near_sdk::json_types::U64;
+use near_sdk::{ext_contract, log, serde_json, Gas, Promise, PromiseResult};
+
+#[ext_contract(ext_self)]
+trait ContractCallbackInterface {
+    fn add_pair_callback(&mut self, pair_data: TradePair, proposal_id: U64) -> bool;
+}
 
 #[near_bindgen]
 impl Contract {
+    /// Stores the pair once ref finance pool & the sell token market were verified.
+    /// Proposal is kept if the verification fails, so it could be applied again or cancelled.
     #[private]
-    pub fn add_pair(&mut self, pair_data: TradePair) {
+    pub fn add_pair_callback(&mut self, pair_data: TradePair, proposal_id: U64) -> bool {
+        let pool_info = match env::promise_result(0) {
+            PromiseResult::Successful(val) => serde_json::from_slice::<PoolInfo>(&val).ok(),
+            _ => None,
+        };
+        let market_data = match env::promise_result(1) {
+            PromiseResult::Successful(val) => serde_json::from_slice::<MarketData>(&val).ok(),
+            _ => None,
+        };
+
+        let verification = match (pool_info, market_data) {
+            (None, _) => Err(format!(
+                "Pool {} not found on ref finance",
+                pair_data.pool_id
+            )),
+            (_, None) => Err(format!(
+                "Market data of {} is not available",
+                pair_data.sell_token_market
+            )),
+            (Some(pool_info), Some(_)) => self
+                .verify_pool(&pair_data, &pool_info)
+                .and_then(|_| self.verify_pair(&pair_data)),
+        };
+
+        if let Err(reason) = verification {
+            log!("Pair wasn't added: {}", reason);
+            return false;
+        }
+
+        self.insert_pair(pair_data);
+        self.proposals.remove(&proposal_id.0);
+        log!("{}", Events::ParameterChangeApplied(proposal_id.0));
+        true
+    }
+}
+
+impl Contract {
+    /// Verifies the pair against ref finance pool & the sell token market before it is stored.
+    pub fn add_pair(&mut self, pair_data: TradePair, proposal_id: u64) -> Promise {
+        self.verify_pair(&pair_data)
+            .unwrap_or_else(|reason| env::panic_str(&reason));
+
+        ext_ref_finance::ext(self.ref_finance_account.clone())
+            .with_static_gas(Gas::ONE_TERA * 5u64)
+            .with_attached_deposit(NO_DEPOSIT)
+            .get_pool(pair_data.pool_id.clone())
+            .and(
+                ext_market::ext(pair_data.sell_token_market.clone())
+                    .with_static_gas(Gas::ONE_TERA * 5u64)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .view_market_data(),
+            )
+            .then(
+                ext_self::ext(current_account_id())
+                    .with_unused_gas_weight(100)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .add_pair_callback(pair_data, U64(proposal_id)),
+            )
+    }
+
+    /// Stores the pair, which clears the record of its previous delisting.
+    pub fn insert_pair(&mut self, pair_data: TradePair) {
         let pair = (pair_data.sell_token.clone(), pair_data.buy_token.clone());
         self.supported_markets.insert(&pair, &pair_data);
+        self.pair_delistings.remove(&pair);
     }
 
-    #[private]
-    pub fn remove_pair(&mut self, pair_data: TradePair) {
-        let pair = (pair_data.sell_token.clone(), pair_data.buy_token);
-        self.supported_markets.remove(&pair);
+    /// Removes the pair, which is possible once all its orders are settled.
+    pub fn remove_pair(&mut self, sell_token: AccountId, buy_token: AccountId) {
+        require!(
+            self.count_unsettled_orders(&sell_token, &buy_token) == 0,
+            "Pair has unsettled orders, it should be delisted first"
+        );
+        self.supported_markets.remove(&(sell_token, buy_token));
+    }
+
+    /// Checks the pair doesn't conflict with the registered token markets & oracle tickers.
+    pub fn verify_pair(&self, pair_data: &TradePair) -> Result<(), String> {
+        if pair_data.sell_token == pair_data.buy_token {
+            return Err("Sell & buy tokens should be different".to_string());
+        }
+        if pair_data.sell_ticker_id == pair_data.buy_ticker_id {
+            return Err("Sell & buy tickers should be different".to_string());
+        }
+        Self::parse_pool_id(&pair_data.pool_id)?;
+
+        match self.tokens_markets.get(&pair_data.sell_token) {
+            Some(market) if market == pair_data.sell_token_market => {}
+            Some(market) => {
+                return Err(format!(
+                    "Market of {} is {}, not {}",
+                    pair_data.sell_token, market, pair_data.sell_token_market
+                ))
+            }
+            None => return Err(format!("Market of {} is not set", pair_data.sell_token)),
+        }
+
+        let ticker_map = self.get_ticker_map();
+        for (ticker_id, token) in [
+            (&pair_data.sell_ticker_id, &pair_data.sell_token),
+            (&pair_data.buy_ticker_id, &pair_data.buy_token),
+        ] {
+            for (registered_ticker, registered_token) in ticker_map.iter() {
+                if registered_ticker == ticker_id && registered_token != token {
+                    return Err(format!(
+                        "Ticker {} is already used by {}",
+                        ticker_id, registered_token
+                    ));
+                }
+                if registered_token == token && registered_ticker != ticker_id {
+                    return Err(format!(
+                        "Token {} is already registered with ticker {}",
+                        token, registered_ticker
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the pool consists of the pair tokens & has the fee tier set in the pool id.
+    pub fn verify_pool(&self, pair_data: &TradePair, pool_info: &PoolInfo) -> Result<(), String> {
+        let (token_x, token_y, fee) = Self::parse_pool_id(&pair_data.pool_id)?;
+
+        if pool_info.pool_id != pair_data.pool_id {
+            return Err(format!(
+                "Pool {} was returned instead of {}",
+                pool_info.pool_id, pair_data.pool_id
+            ));
+        }
+        if pool_info.token_x != token_x || pool_info.token_y != token_y {
+            return Err(format!(
+                "Pool tokens {} & {} don't match the pool id",
+                pool_info.token_x, pool_info.token_y
+            ));
+        }
+        // sell token could be either of the pool tokens, as order liquidity is placed on its side
+        let pair_tokens = [&pair_data.sell_token, &pair_data.buy_token];
+        if !pair_tokens.contains(&&token_x) || !pair_tokens.contains(&&token_y) {
+            return Err(format!(
+                "Pool {} doesn't consist of {} & {}",
+                pair_data.pool_id, pair_data.sell_token, pair_data.buy_token
+            ));
+        }
+        if pool_info.fee != fee {
+            return Err(format!(
+                "Pool fee {} doesn't match the fee tier {}",
+                pool_info.fee, fee
+            ));
+        }
+        if pool_info.state != PoolState::Running {
+            return Err(format!("Pool {} is not running", pair_data.pool_id));
+        }
+
+        Ok(())
+    }
+
+    /// Parses ref finance DCL pool id in the `token_x|token_y|fee` format.
+    pub fn parse_pool_id(pool_id: &str) -> Result<(AccountId, AccountId, u64), String> {
+        let parts = pool_id.split('|').collect::<Vec<&str>>();
+        let invalid_pool_id = || format!("Invalid pool id {}", pool_id);
+        if parts.len() != 3 {
+            return Err(invalid_pool_id());
+        }
+
+        let token_x = parts[0].parse().map_err(|_| invalid_pool_id())?;
+        let token_y = parts[1].parse().map_err(|_| invalid_pool_id())?;
+        let fee = parts[2].parse().map_err(|_| invalid_pool_id())?;
+        Ok((token_x, token_y, fee))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn get_pair() -> TradePair {
+        TradePair {
+            sell_ticker_id: "usdt".to_string(),
+            sell_token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            sell_token_market: "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            buy_ticker_id: "wnear".to_string(),
+            buy_token: "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
+        }
+    }
+
+    fn get_contract() -> Contract {
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        contract.insert_token_market(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_pair_market_and_tickers_are_verified() {
+        let mut contract = get_contract();
+        assert_eq!(contract.verify_pair(&get_pair()), Ok(()));
+
+        let pair = TradePair {
+            sell_token_market: "wnear_market.qa.v1.nearlend.testnet".parse().unwrap(),
+            ..get_pair()
+        };
+        assert!(contract.verify_pair(&pair).is_err());
+
+        contract.insert_pair(get_pair());
+        let pair = TradePair {
+            buy_ticker_id: "usdt".to_string(),
+            buy_token: "eth.qa.v1.nearlend.testnet".parse().unwrap(),
+            pool_id: "usdt.qa.v1.nearlend.testnet|eth.qa.v1.nearlend.testnet|2000".to_string(),
+            sell_ticker_id: "tether".to_string(),
+            ..get_pair()
+        };
+        assert!(contract.verify_pair(&pair).is_err());
+    }
+
+    #[test]
+    fn test_pool_is_verified() {
+        let contract = get_contract();
+        let mut pool_info: PoolInfo = serde_json::from_str(r#"{"pool_id":"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000","token_x":"usdt.qa.v1.nearlend.testnet","token_y":"wnear.qa.v1.nearlend.testnet","fee":2000,"point_delta":40,"current_point":-11333,"liquidity":"0","liquidity_x":"0","max_liquidity_per_point":"0","volume_x_in":"0","volume_y_in":"0","volume_x_out":"0","volume_y_out":"0","total_liquidity":"0","total_order_x":"0","total_order_y":"0","total_x":"0","total_y":"0","state":"Running"}"#).unwrap();
+        assert_eq!(contract.verify_pool(&get_pair(), &pool_info), Ok(()));
+
+        pool_info.fee = 400;
+        assert_eq!(
+            contract.verify_pool(&get_pair(), &pool_info),
+            Err("Pool fee 400 doesn't match the fee tier 2000".to_string())
+        );
+
+        let mut reversed_pair = get_pair();
+        reversed_pair.sell_token = "wnear.qa.v1.nearlend.testnet".parse().unwrap();
+        reversed_pair.buy_token = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        pool_info.fee = 2000;
+        assert_eq!(contract.verify_pool(&reversed_pair, &pool_info), Ok(()));
+
+        reversed_pair.buy_token = "eth.qa.v1.nearlend.testnet".parse().unwrap();
+        assert_eq!(
+            contract.verify_pool(&reversed_pair, &pool_info),
+            Err("Pool usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000 doesn't consist of wnear.qa.v1.nearlend.testnet & eth.qa.v1.nearlend.testnet".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pair_is_not_added_if_pool_is_missing() {
+        let mut contract = get_contract();
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed, PromiseResult::Failed],
+        );
+
+        assert!(!contract.add_pair_callback(get_pair(), U64(0)));
+        assert!(contract.view_supported_pairs().is_empty());
     }
 }
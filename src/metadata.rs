@@ -1,5 +1,6 @@
 use crate::big_decimal::{BigDecimal, WBalance, WRatio};
 use crate::*;
+use near_sdk::json_types::U64;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{AccountId, Balance, BlockHeight, BorshStorageKey};
 use std::fmt;
@@ -13,6 +14,18 @@ pub enum StorageKeys {
     Balances,
     TokenMarkets,
     ProtocolProfit,
+    PendingOperations,
+    PairFees,
+    AccountVolumes,
+    ReferralCodes,
+    Referrers,
+    ReferralStats,
+    KeeperStats,
+    Roles,
+    Proposals,
+    PairDelistings,
+    PendingSwaps,
+    MinTokenBounties,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
@@ -60,6 +73,12 @@ pub enum OrderStatus {
     Executed,
     Canceled,
     Liquidated,
+    /// Order is being executed, ref finance liquidity removal is in progress
+    Executing,
+    /// Order is being canceled by the user
+    Closing,
+    /// Order is being liquidated
+    Liquidating,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -82,6 +101,26 @@ pub struct Order {
     pub buy_token_price: Price,
     pub block: BlockHeight,
     pub lpt_id: String,
+    pub left_point: i32,
+    pub right_point: i32,
+    /// Sell token amount returned by ref finance on execution for the not crossed part of the range
+    pub remaining_sell_amount: Balance,
+    /// Buy token amount received from ref finance on execution
+    pub filled_buy_amount: Balance,
+    /// Sell token amount converted into the buy token on execution
+    pub filled_sell_amount: Balance,
+    /// Block the order was executed at
+    pub execution_block: BlockHeight,
+    /// Effective execution price of the buy token in the sell token
+    pub execution_price: BigDecimal,
+    /// Owner share of ref finance pool fees earned by the order liquidity in the sell token
+    pub accrued_fee_x: Balance,
+    /// Owner share of ref finance pool fees earned by the order liquidity in the buy token
+    pub accrued_fee_y: Balance,
+    /// Bounty escrowed for the executor or liquidator of the order
+    pub executor_bounty: Balance,
+    /// Token of the bounty, NEAR if not specified
+    pub bounty_token: Option<AccountId>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Debug)]
@@ -97,6 +136,34 @@ pub struct OrderView {
     pub buy_token_price: WBalance,
     pub fee: WBalance,
     pub lpt_id: String,
+    pub accrued_fee_x: WBalance,
+    pub accrued_fee_y: WBalance,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiquidatableOrderView {
+    pub account_id: AccountId,
+    pub order_id: U128,
+    pub status: OrderStatus,
+    pub sell_token: AccountId,
+    pub buy_token: AccountId,
+    pub amount: U128,
+    pub liquidator_reward: WBalance,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExecutableOrderView {
+    pub account_id: AccountId,
+    pub order_id: U128,
+    pub amount: U128,
+    pub lpt_id: String,
+    pub left_point: i32,
+    pub right_point: i32,
+    pub executor_reward: WBalance,
+    /// Token of the executor reward, NEAR if not specified
+    pub executor_reward_token: Option<AccountId>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -126,6 +193,10 @@ pub enum OrderAction {
     Create,
     Cancel,
     Liquidate,
+    /// Cancel of the delisted pair order by the keeper, who is paid the escrowed bounty
+    ForceClose {
+        keeper: AccountId,
+    },
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq)]
@@ -171,11 +242,312 @@ pub struct Liquidity {
     pub unclaimed_fee_y: U128,
 }
 
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum OperationKind {
+    /// Deposit & add liquidity to ref finance on order creation
+    AddLiquidity,
+    /// Repay of the borrowed assets to the market
+    Repay,
+}
+
+/// Swap of the order filled buy token back into the sell token awaiting its output from ref finance
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PendingSwap {
+    pub order: Order,
+    pub order_action: OrderAction,
+    pub account_id: AccountId,
+    /// Market data fetched before the position was touched to settle the debt with
+    pub market_data: MarketData,
+    /// Sell token amount received from ref finance for the swap
+    pub output: Option<Balance>,
+    /// Whether ref finance used the swapped buy token
+    pub is_input_used: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingOperation {
+    pub kind: OperationKind,
+    pub account_id: AccountId,
+    pub token: AccountId,
+    /// Amount of tokens sent to the external contract
+    pub amount: WBalance,
+    /// User balance reserved by the operation to be restored on failure
+    pub reserved_balance: WBalance,
+}
+
+/// Fee ratios of the trade pair in 24 decimals
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSchedule {
+    /// Taken from the order amount on creation
+    pub opening_fee: WRatio,
+    /// Taken from the position value on cancel & liquidation
+    pub closing_fee: WRatio,
+    /// Taken from the profit on cancel
+    pub profit_share_fee: WRatio,
+    /// Taken from the order amount on liquidation
+    pub liquidation_fee: WRatio,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self {
+            opening_fee: U128(0),
+            closing_fee: U128(3 * 10_u128.pow(20)),
+            profit_share_fee: U128(10_u128.pow(23)),
+            liquidation_fee: U128(0),
+        }
+    }
+}
+
+/// Discount of opening & closing fees for the accounts with at least `min_volume`
+/// traded within the rolling window
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeTier {
+    /// Trade volume in the reference quote currency
+    pub min_volume: WBalance,
+    pub fee_discount: WRatio,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeTierView {
+    pub account_id: AccountId,
+    /// Trade volume within the rolling window in the reference quote currency
+    pub volume: WBalance,
+    /// Index of the applied tier, None if no tier is reached
+    pub tier: Option<u32>,
+    pub fee_discount: WRatio,
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Default,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralStats {
+    /// Count of accounts registered with the referrer code
+    pub referred_accounts: u64,
+    /// token ➝ referral fees credited to the referrer balance
+    pub earnings: HashMap<AccountId, WBalance>,
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    /// Manages pairs, markets, fees & roles except the admin one
+    Admin,
+    /// Manages risk parameters
+    RiskManager,
+    /// Updates prices & market data
+    PriceFeeder,
+    /// Pauses the contract in emergency
+    Pauser,
+    /// Cancels scheduled parameter changes
+    Guardian,
+}
+
+/// Delisting of the pair, which is kept after the pair removal as its settlement record
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PairDelisting {
+    pub pair_data: TradePair,
+    /// Block timestamp the pair was switched to reduce-only mode at
+    pub started_at: u64,
+    /// Block timestamp open orders of the pair could be force closed since
+    pub force_close_from: u64,
+    /// Block timestamp the pair was removed at once all its orders were settled
+    pub removed_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DelistingStatus {
+    /// New orders can't be created, owners are able to cancel the open ones
+    ReduceOnly,
+    /// Open orders could be force closed by keepers
+    ForceClose,
+    /// All the orders were settled & the pair was removed
+    Delisted,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DelistingView {
+    pub pair_data: TradePair,
+    pub status: DelistingStatus,
+    pub started_at: u64,
+    pub force_close_from: u64,
+    pub removed_at: Option<u64>,
+    /// Orders of the pair which are not canceled or liquidated yet
+    pub unsettled_orders: u64,
+}
+
+/// Parameter change, which is applied after the timelock delay
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ParameterChange {
+    LiquidationThreshold {
+        threshold: U128,
+    },
+    VolatilityRate {
+        rate: U128,
+    },
+    DefaultFees {
+        fees: FeeSchedule,
+    },
+    PairFees {
+        sell_token: AccountId,
+        buy_token: AccountId,
+        fees: FeeSchedule,
+    },
+    RemovePairFees {
+        sell_token: AccountId,
+        buy_token: AccountId,
+    },
+    AddPair {
+        pair_data: TradePair,
+    },
+    RemovePair {
+        sell_token: AccountId,
+        buy_token: AccountId,
+    },
+    OracleAccount {
+        account_id: AccountId,
+    },
+    RefFinanceAccount {
+        account_id: AccountId,
+    },
+    TimelockDelay {
+        delay: U64,
+    },
+    SlippageTolerance {
+        tolerance: U128,
+    },
+    FeeTiers {
+        tiers: Vec<FeeTier>,
+    },
+    PoolFeeOwnerShare {
+        share: U128,
+    },
+    ReferralShare {
+        share: U128,
+    },
+    MinExecutorBounty {
+        bounty: U128,
+    },
+    MinTokenBounty {
+        token: AccountId,
+        bounty: U128,
+    },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ParameterProposal {
+    pub change: ParameterChange,
+    pub proposer: AccountId,
+    /// Block timestamp the proposal was created at
+    pub proposed_at: u64,
+    /// Block timestamp the change could be applied since
+    pub executable_at: u64,
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PauseAction {
+    CreateOrder,
+    ExecuteOrder,
+    CancelOrder,
+    Liquidate,
+    Deposit,
+    /// Claim of the keeper rewards, as there is no withdrawal of the user balance
+    ClaimRewards,
+}
+
+impl PauseAction {
+    /// Actions which are still available while the contract or the pair is paused
+    pub fn is_risk_reducing(&self) -> bool {
+        matches!(
+            self,
+            PauseAction::CancelOrder | PauseAction::Liquidate | PauseAction::ClaimRewards
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseStatus {
+    pub paused: bool,
+    pub paused_pairs: Vec<(AccountId, AccountId)>,
+    pub paused_actions: Vec<PauseAction>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum KeeperAction {
+    Execute,
+    Liquidate,
+    ForceClose,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct KeeperStats {
+    pub executions: u64,
+    pub liquidations: u64,
+    /// NEAR rewards earned in total
+    pub earned_near: WBalance,
+    /// token ➝ rewards earned in total
+    pub earned_tokens: HashMap<AccountId, WBalance>,
+    /// NEAR rewards to be claimed
+    pub unclaimed_near: WBalance,
+    /// token ➝ rewards to be claimed
+    pub unclaimed_tokens: HashMap<AccountId, WBalance>,
+}
+
+impl Default for KeeperStats {
+    fn default() -> Self {
+        Self {
+            executions: 0,
+            liquidations: 0,
+            earned_near: U128(0),
+            earned_tokens: HashMap::new(),
+            unclaimed_near: U128(0),
+            unclaimed_tokens: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 #[derive(Debug)]
 pub enum Actions {
-    Deposit { token: AccountId },
+    /// Deposits transferred tokens to the `beneficiary` balance, sender balance is used if not specified.
+    Deposit {
+        token: AccountId,
+        beneficiary: Option<AccountId>,
+    },
+    /// Deposits transferred tokens & places the order from them.
+    /// `amount` is the order amount, the transferred amount is used if not specified.
+    CreateOrder {
+        order_type: OrderType,
+        sell_token: AccountId,
+        buy_token: AccountId,
+        leverage: U128,
+        amount: Option<U128>,
+        referral_code: Option<String>,
+        /// Executor bounty in the transferred token
+        executor_bounty: Option<U128>,
+    },
+    /// Output of the order swap sent back by ref finance
+    SwapOutput { order_id: U128 },
 }
 
 impl fmt::Display for Actions {
@@ -0,0 +1,286 @@
+use crate::*;
+use near_sdk::BlockHeight;
+
+/// Order layout before the partial fill, fee & bounty tracking
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OrderV1 {
+    pub status: OrderStatus,
+    pub order_type: OrderType,
+    pub amount: Balance,
+    pub sell_token: AccountId,
+    pub buy_token: AccountId,
+    pub leverage: BigDecimal,
+    pub sell_token_price: Price,
+    pub buy_token_price: Price,
+    pub block: BlockHeight,
+    pub lpt_id: String,
+}
+
+impl From<OrderV1> for Order {
+    /// Order range isn't known, so it is taken from ref finance on execution.
+    /// Executed order was fully filled at the order prices as required by the previous execution.
+    fn from(order: OrderV1) -> Self {
+        let position_amount = BigDecimal::from(U128(order.amount)) * order.leverage;
+        let (filled_sell_amount, filled_buy_amount, execution_price) =
+            if order.status == OrderStatus::Pending {
+                (0, 0, BigDecimal::zero())
+            } else {
+                let filled_buy_amount =
+                    position_amount * order.sell_token_price.value / order.buy_token_price.value;
+                (
+                    WBalance::from(position_amount).0,
+                    WBalance::from(filled_buy_amount).0,
+                    order.buy_token_price.value / order.sell_token_price.value,
+                )
+            };
+
+        Self {
+            status: order.status,
+            order_type: order.order_type,
+            amount: order.amount,
+            sell_token: order.sell_token,
+            buy_token: order.buy_token,
+            leverage: order.leverage,
+            sell_token_price: order.sell_token_price,
+            buy_token_price: order.buy_token_price,
+            block: order.block,
+            lpt_id: order.lpt_id,
+            left_point: 0,
+            right_point: 0,
+            remaining_sell_amount: 0,
+            filled_buy_amount,
+            filled_sell_amount,
+            execution_block: order.block,
+            execution_price,
+            accrued_fee_x: 0,
+            accrued_fee_y: 0,
+            executor_bounty: 0,
+            bounty_token: None,
+        }
+    }
+}
+
+/// Contract layout before the fee schedules, keepers, referrals, access control,
+/// pause, timelock & delisting were added
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub market_infos: LookupMap<AccountId, MarketData>,
+    pub protocol_fee: u128,
+    pub prices: UnorderedMap<AccountId, Price>,
+    pub order_nonce: u64,
+    pub orders: UnorderedMap<AccountId, HashMap<u64, OrderV1>>,
+    pub supported_markets: UnorderedMap<(AccountId, AccountId), TradePair>,
+    pub balances: UnorderedMap<AccountId, HashMap<AccountId, Balance>>,
+    pub config: Config,
+    pub tokens_markets: LookupMap<AccountId, AccountId>,
+    pub protocol_profit: LookupMap<AccountId, BigDecimal>,
+    pub ref_finance_account: AccountId,
+    pub liquidation_threshold: u128,
+    pub volatility_rate: BigDecimal,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Migrates the state from the previous layout. Needs to be called once right after the upgrade.
+    ///
+    /// Protocol fee becomes the default profit share fee, while the new parameters are set to defaults.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate() -> Self {
+        let mut old: ContractV1 =
+            env::state_read().unwrap_or_else(|| env::panic_str("Contract state is not found"));
+
+        // orders are stored under the same prefix, so the old ones are removed before reinserting
+        let orders = old.orders.to_vec();
+        old.orders.clear();
+
+        // profit lookup map can't be iterated, so it is moved for the tokens of the supported pairs.
+        // It was accrued from the token amounts wrapped into decimals, so it keeps the raw amount.
+        let mut tokens: Vec<AccountId> = Vec::new();
+        for pair in old.supported_markets.values() {
+            for token in [pair.sell_token, pair.buy_token] {
+                if !tokens.contains(&token) {
+                    tokens.push(token);
+                }
+            }
+        }
+        let profits = tokens
+            .into_iter()
+            .filter_map(|token| {
+                old.protocol_profit
+                    .remove(&token)
+                    .map(|profit| (token, WBalance::from(profit).0))
+            })
+            .collect::<Vec<(AccountId, Balance)>>();
+
+        let mut contract = Self::init_state(old.config);
+        contract.market_infos = old.market_infos;
+        contract.default_fees.profit_share_fee = U128(old.protocol_fee);
+        contract.prices = old.prices;
+        contract.order_nonce = old.order_nonce;
+        contract.supported_markets = old.supported_markets;
+        contract.balances = old.balances;
+        contract.tokens_markets = old.tokens_markets;
+        contract.ref_finance_account = old.ref_finance_account;
+        contract.liquidation_threshold = old.liquidation_threshold;
+        contract.volatility_rate = old.volatility_rate;
+
+        for (account_id, orders) in orders {
+            let orders = orders
+                .into_iter()
+                .map(|(order_id, order)| (order_id, Order::from(order)))
+                .collect::<HashMap<u64, Order>>();
+            contract.orders.insert(&account_id, &orders);
+        }
+
+        for (token, profit) in profits {
+            contract.add_protocol_profit(&token, profit);
+        }
+
+        contract
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, VMContext};
+
+    fn get_context() -> VMContext {
+        VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .predecessor_account_id("margin.nearland.testnet".parse().unwrap())
+            .block_index(103930920)
+            .build()
+    }
+
+    fn get_old_contract() -> ContractV1 {
+        let usdt: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        let wnear: AccountId = "wnear.qa.v1.nearlend.testnet".parse().unwrap();
+
+        let mut old = ContractV1 {
+            market_infos: LookupMap::new(StorageKeys::Markets),
+            protocol_fee: 2 * 10_u128.pow(23),
+            prices: UnorderedMap::new(StorageKeys::Prices),
+            order_nonce: 1,
+            orders: UnorderedMap::new(StorageKeys::Orders),
+            supported_markets: UnorderedMap::new(StorageKeys::SupportedMarkets),
+            balances: UnorderedMap::new(StorageKeys::Balances),
+            config: Config {
+                owner_id: "owner_id.testnet".parse().unwrap(),
+                oracle_account_id: "oracle_account_id.testnet".parse().unwrap(),
+            },
+            tokens_markets: LookupMap::new(StorageKeys::TokenMarkets),
+            protocol_profit: LookupMap::new(StorageKeys::ProtocolProfit),
+            ref_finance_account: "dcl.ref-dev.testnet".parse().unwrap(),
+            liquidation_threshold: 10_u128.pow(23),
+            volatility_rate: BigDecimal::from(U128(95 * 10_u128.pow(22))),
+        };
+
+        old.supported_markets.insert(
+            &(usdt.clone(), wnear.clone()),
+            &TradePair {
+                sell_ticker_id: "usdt".to_string(),
+                sell_token: usdt.clone(),
+                sell_token_market: "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+                buy_ticker_id: "wnear".to_string(),
+                buy_token: wnear.clone(),
+                pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000"
+                    .to_string(),
+            },
+        );
+        // profit is accrued the way the previous cancel did: from the token amounts wrapped into decimals
+        let expect_amount = BigDecimal::from(U128(2025 * 10_u128.pow(24)));
+        let sell_amount =
+            BigDecimal::from(1.0) * BigDecimal::from(U128(10_u128.pow(27))) * BigDecimal::from(2.0);
+        old.protocol_profit.insert(
+            &usdt,
+            &(BigDecimal::from(U128(0)) + expect_amount - sell_amount),
+        );
+        old.balances.insert(
+            &alice(),
+            &HashMap::from([(usdt.clone(), 300 * 10_u128.pow(24))]),
+        );
+
+        let order = OrderV1 {
+            status: OrderStatus::Executed,
+            order_type: OrderType::Buy,
+            amount: 10_u128.pow(27),
+            sell_token: usdt,
+            buy_token: wnear,
+            leverage: BigDecimal::from(2.0),
+            sell_token_price: Price {
+                ticker_id: "USDT".to_string(),
+                value: BigDecimal::from(1.0),
+            },
+            buy_token_price: Price {
+                ticker_id: "WNEAR".to_string(),
+                value: BigDecimal::from(4.0),
+            },
+            block: 103930916,
+            lpt_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#1".to_string(),
+        };
+        let pending_order = OrderV1 {
+            status: OrderStatus::Pending,
+            order_type: OrderType::Buy,
+            amount: order.amount,
+            sell_token: order.sell_token.clone(),
+            buy_token: order.buy_token.clone(),
+            leverage: order.leverage,
+            sell_token_price: order.sell_token_price.clone(),
+            buy_token_price: order.buy_token_price.clone(),
+            block: 103930918,
+            lpt_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#2".to_string(),
+        };
+        old.orders
+            .insert(&alice(), &HashMap::from([(1, order), (2, pending_order)]));
+        old
+    }
+
+    #[test]
+    fn migrate_test() {
+        testing_env!(get_context());
+        env::state_write(&get_old_contract());
+
+        let contract = Contract::migrate();
+
+        assert_eq!(contract.order_nonce, 1);
+        assert_eq!(
+            contract.default_fees.profit_share_fee,
+            U128(2 * 10_u128.pow(23))
+        );
+        assert_eq!(contract.view_owner(), "owner_id.testnet".parse().unwrap());
+        assert_eq!(contract.supported_markets.len(), 1);
+
+        // executed order was fully filled at the order prices
+        let order = contract.get_order_by(1).unwrap();
+        assert_eq!(order.status, OrderStatus::Executed);
+        assert_eq!(order.filled_sell_amount, 2 * 10_u128.pow(27));
+        assert_eq!(order.filled_buy_amount, 5 * 10_u128.pow(26));
+        assert_eq!(order.executor_bounty, 0);
+
+        // pending order keeps the whole position in sell token
+        let order = contract.get_order_by(2).unwrap();
+        assert_eq!(order.status, OrderStatus::Pending);
+        assert_eq!(order.filled_buy_amount, 0);
+        assert_eq!(order.lpt_id.split('#').nth(1), Some("2"));
+
+        // profit of 25 tokens is moved to the new ledger without being rescaled
+        assert_eq!(
+            contract.view_protocol_profit("usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            U128(25 * 10_u128.pow(24))
+        );
+        assert_eq!(
+            contract.balance_of(alice(), "usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            300 * 10_u128.pow(24)
+        );
+        assert!(
+            LookupMap::<AccountId, BigDecimal>::new(StorageKeys::ProtocolProfit)
+                .get(&"usdt.qa.v1.nearlend.testnet".parse().unwrap())
+                .is_none()
+        );
+    }
+}
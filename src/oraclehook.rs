@@ -32,14 +32,14 @@ impl OraclePriceHandlerHook for Contract {
 
         for price in price_data.price_list {
             if let Some(token) = ticker_map.get(&price.ticker_id) {
-                self.update_or_insert_price(token.clone(), price.clone())
+                self.insert_price(token, &price)
             }
         }
     }
 }
 
 impl Contract {
-    pub fn get_ticker_map(&mut self) -> HashMap<String, AccountId> {
+    pub fn get_ticker_map(&self) -> HashMap<String, AccountId> {
         let mut ticker_map = HashMap::new();
         self.supported_markets.values().for_each(|trade_pair| {
             ticker_map.insert(trade_pair.sell_ticker_id, trade_pair.sell_token);
@@ -0,0 +1,130 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Pauses all the actions increasing the risk: order creation & execution and deposits.
+    /// Cancel, liquidation & reward claims are still available unless paused explicitly.
+    pub fn pause(&mut self) {
+        self.assert_role(Role::Pauser);
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.assert_role(Role::Admin);
+        self.paused = false;
+    }
+
+    /// Pauses order creation & execution for the pair.
+    pub fn pause_pair(&mut self, sell_token: AccountId, buy_token: AccountId) {
+        self.assert_role(Role::Pauser);
+        let pair = (sell_token, buy_token);
+        if !self.paused_pairs.contains(&pair) {
+            self.paused_pairs.push(pair);
+        }
+    }
+
+    pub fn unpause_pair(&mut self, sell_token: AccountId, buy_token: AccountId) {
+        self.assert_role(Role::Admin);
+        let pair = (sell_token, buy_token);
+        self.paused_pairs.retain(|paused_pair| *paused_pair != pair);
+    }
+
+    /// Pauses the action for all the pairs.
+    pub fn pause_action(&mut self, action: PauseAction) {
+        self.assert_role(Role::Pauser);
+        if !self.paused_actions.contains(&action) {
+            self.paused_actions.push(action);
+        }
+    }
+
+    pub fn unpause_action(&mut self, action: PauseAction) {
+        self.assert_role(Role::Admin);
+        self.paused_actions
+            .retain(|paused_action| *paused_action != action);
+    }
+
+    pub fn view_pause_status(&self) -> PauseStatus {
+        PauseStatus {
+            paused: self.paused,
+            paused_pairs: self.paused_pairs.clone(),
+            paused_actions: self.paused_actions.clone(),
+        }
+    }
+}
+
+impl Contract {
+    /// Fails if the action is paused explicitly, by the global pause or for the given pair.
+    pub fn assert_not_paused(&self, action: PauseAction, pair: Option<(&AccountId, &AccountId)>) {
+        require!(
+            !self.paused_actions.contains(&action),
+            format!("{:?} action is paused", action)
+        );
+
+        if action.is_risk_reducing() {
+            return;
+        }
+
+        require!(!self.paused, "Contract is paused");
+        if let Some((sell_token, buy_token)) = pair {
+            require!(
+                !self
+                    .paused_pairs
+                    .contains(&(sell_token.clone(), buy_token.clone())),
+                format!("Pair {}-{} is paused", sell_token, buy_token)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    fn get_contract() -> Contract {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .build());
+        Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_global_pause_allows_risk_reducing_actions() {
+        let mut contract = get_contract();
+        let sell_token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        let buy_token: AccountId = "wnear.qa.v1.nearlend.testnet".parse().unwrap();
+
+        contract.pause();
+        contract.assert_not_paused(PauseAction::CancelOrder, Some((&sell_token, &buy_token)));
+        contract.assert_not_paused(PauseAction::Liquidate, Some((&sell_token, &buy_token)));
+        contract.assert_not_paused(PauseAction::ClaimRewards, None);
+
+        contract.unpause();
+        contract.pause_pair(sell_token.clone(), buy_token.clone());
+        contract.assert_not_paused(PauseAction::CreateOrder, Some((&buy_token, &sell_token)));
+        contract.assert_not_paused(PauseAction::CancelOrder, Some((&sell_token, &buy_token)));
+
+        contract.unpause_pair(sell_token.clone(), buy_token.clone());
+        contract.assert_not_paused(PauseAction::CreateOrder, Some((&sell_token, &buy_token)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_global_pause_blocks_order_creation() {
+        let mut contract = get_contract();
+        contract.pause();
+        contract.assert_not_paused(PauseAction::Deposit, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ClaimRewards action is paused")]
+    fn test_explicitly_paused_action_is_blocked() {
+        let mut contract = get_contract();
+        contract.pause_action(PauseAction::ClaimRewards);
+        contract.assert_not_paused(PauseAction::ClaimRewards, None);
+    }
+}
@@ -3,9 +3,9 @@ use crate::*;
 
 #[near_bindgen]
 impl Contract {
-    #[private]
     pub fn update_or_insert_price(&mut self, token_id: AccountId, price: Price) {
-        self.prices.insert(&token_id, &price);
+        self.assert_role(Role::PriceFeeder);
+        self.insert_price(&token_id, &price);
     }
 
     pub fn get_price(&self, token_id: AccountId) -> BigDecimal {
@@ -21,6 +21,10 @@ impl Contract {
         self.view_price(token_id_1).value / self.view_price(token_id_2).value
     }
 
+    pub fn insert_price(&mut self, token_id: &AccountId, price: &Price) {
+        self.prices.insert(token_id, price);
+    }
+
     pub fn get_market_by(&self, token: &AccountId) -> AccountId {
         self.tokens_markets.get(&token).unwrap_or_else(|| {
             panic!("Market for token: {} was not found", token);
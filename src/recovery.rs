@@ -0,0 +1,252 @@
+use crate::events::Events;
+use crate::ref_finance::ext_ref_finance;
+use crate::utils::{ext_token, NO_DEPOSIT};
+use crate::*;
+use near_sdk::env::current_account_id;
+use near_sdk::{
+    ext_contract, is_promise_success, log, serde_json, Gas, Promise, PromiseResult, ONE_YOCTO,
+};
+
+#[ext_contract(ext_self)]
+trait ContractCallbackInterface {
+    fn withdraw_asset_callback(&mut self, operation_id: u64);
+    fn repay_callback(&mut self, operation_id: u64);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Retries the failed operation: withdraws tokens stuck on ref finance or repays the debt again.
+    pub fn retry_operation(&mut self, operation_id: u64) -> Promise {
+        self.assert_role(Role::Admin);
+        let operation = self
+            .pending_operations
+            .get(&operation_id)
+            .unwrap_or_else(|| {
+                panic!("Operation with id: {} not found", operation_id);
+            });
+
+        match operation.kind {
+            OperationKind::AddLiquidity => self.withdraw_from_ref(operation_id, &operation),
+            OperationKind::Repay => self.send_repay(operation_id, &operation),
+        }
+    }
+
+    #[private]
+    pub fn withdraw_asset_callback(&mut self, operation_id: u64) {
+        if !is_promise_success() {
+            log!(
+                "{}",
+                Events::OperationFailed(
+                    operation_id,
+                    OperationKind::AddLiquidity,
+                    "failed to withdraw asset from ref finance".to_string()
+                )
+            );
+            return;
+        }
+
+        self.recover_operation(operation_id);
+    }
+
+    /// Keeps the not repaid part of the debt as pending operation, so it could be retried,
+    /// as the amount not used by the market is refunded to the contract.
+    #[private]
+    pub fn repay_callback(&mut self, operation_id: u64) {
+        let used_amount = match env::promise_result(0) {
+            PromiseResult::Successful(val) => serde_json::from_slice::<U128>(&val)
+                .map(|amount| amount.0)
+                .unwrap_or(0),
+            _ => 0,
+        };
+
+        let mut operation = match self.pending_operations.get(&operation_id) {
+            Some(operation) => operation,
+            None => return,
+        };
+
+        if used_amount >= operation.amount.0 {
+            self.pending_operations.remove(&operation_id);
+            return;
+        }
+
+        log!(
+            "{}",
+            Events::OperationFailed(
+                operation_id,
+                OperationKind::Repay,
+                format!("repaid {} of {} assets", used_amount, operation.amount.0)
+            )
+        );
+        operation.amount = U128(operation.amount.0 - used_amount);
+        self.pending_operations.insert(&operation_id, &operation);
+    }
+
+    pub fn view_pending_operations(&self) -> Vec<(u64, PendingOperation)> {
+        self.pending_operations.to_vec()
+    }
+}
+
+impl Contract {
+    /// Records operation with irreversible external effect, so it can be recovered on failure.
+    pub fn add_pending_operation(&mut self, operation: PendingOperation) -> u64 {
+        self.operation_nonce += 1;
+        self.pending_operations
+            .insert(&self.operation_nonce, &operation);
+        self.operation_nonce
+    }
+
+    /// Restores user balance reserved by the failed operation & removes it from pending ones.
+    pub fn recover_operation(&mut self, operation_id: u64) {
+        if let Some(operation) = self.pending_operations.remove(&operation_id) {
+            if operation.reserved_balance.0 > 0 {
+                self.increase_balance(
+                    &operation.account_id,
+                    &operation.token,
+                    operation.reserved_balance.0,
+                );
+            }
+
+            log!(
+                "{}",
+                Events::OperationRecovered(operation_id, operation.kind)
+            );
+        }
+    }
+
+    /// Withdraws tokens deposited on ref finance by the failed operation back to the contract.
+    pub fn withdraw_from_ref(&self, operation_id: u64, operation: &PendingOperation) -> Promise {
+        ext_ref_finance::ext(self.ref_finance_account.clone())
+            .with_static_gas(Gas::ONE_TERA * 50u64)
+            .with_attached_deposit(ONE_YOCTO)
+            .withdraw_asset(operation.token.clone(), Some(operation.amount))
+            .then(
+                ext_self::ext(current_account_id())
+                    .with_static_gas(Gas::ONE_TERA * 10u64)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .withdraw_asset_callback(operation_id),
+            )
+    }
+
+    /// Transfers operation amount of debt to the token market.
+    pub fn send_repay(&self, operation_id: u64, operation: &PendingOperation) -> Promise {
+        ext_token::ext(operation.token.clone())
+            .with_static_gas(Gas::ONE_TERA * 35u64)
+            .with_attached_deposit(ONE_YOCTO)
+            .ft_transfer_call(
+                self.get_market_by(&operation.token),
+                operation.amount,
+                None,
+                "\"Repay\"".to_string(),
+            )
+            .then(
+                ext_self::ext(current_account_id())
+                    .with_static_gas(Gas::ONE_TERA * 3u64)
+                    .with_attached_deposit(NO_DEPOSIT)
+                    .repay_callback(operation_id),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, VMContext};
+
+    fn get_context() -> VMContext {
+        VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .predecessor_account_id("margin.nearland.testnet".parse().unwrap())
+            .signer_account_id(alice())
+            .build()
+    }
+
+    fn add_repay(contract: &mut Contract) -> u64 {
+        contract.add_pending_operation(PendingOperation {
+            kind: OperationKind::Repay,
+            account_id: alice(),
+            token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            amount: U128(2000),
+            reserved_balance: U128(0),
+        })
+    }
+
+    fn set_repay_result(result: PromiseResult) {
+        testing_env!(
+            get_context(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    #[test]
+    fn test_recover_operation_restores_balance() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("margin.nearland.testnet".parse().unwrap())
+            .signer_account_id(alice())
+            .build());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+
+        let operation_id = contract.add_pending_operation(PendingOperation {
+            kind: OperationKind::AddLiquidity,
+            account_id: alice(),
+            token: token.clone(),
+            amount: U128(2000),
+            reserved_balance: U128(1000),
+        });
+        assert_eq!(contract.view_pending_operations().len(), 1);
+
+        contract.recover_operation(operation_id);
+
+        assert_eq!(contract.balance_of(alice(), token), 1000);
+        assert!(contract.view_pending_operations().is_empty());
+    }
+
+    #[test]
+    fn test_partially_refunded_repay_is_kept_pending() {
+        testing_env!(get_context());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let operation_id = add_repay(&mut contract);
+
+        // market used only a part of the transferred amount, the rest was refunded
+        set_repay_result(PromiseResult::Successful(
+            serde_json::to_vec(&U128(1500)).unwrap(),
+        ));
+        contract.repay_callback(operation_id);
+        let operations = contract.view_pending_operations();
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations[0].1.amount, U128(500));
+
+        // the rest is repaid on retry
+        set_repay_result(PromiseResult::Successful(
+            serde_json::to_vec(&U128(500)).unwrap(),
+        ));
+        contract.repay_callback(operation_id);
+        assert!(contract.view_pending_operations().is_empty());
+    }
+
+    #[test]
+    fn test_failed_repay_is_kept_pending() {
+        testing_env!(get_context());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let operation_id = add_repay(&mut contract);
+
+        set_repay_result(PromiseResult::Failed);
+        contract.repay_callback(operation_id);
+        assert_eq!(contract.view_pending_operations()[0].1.amount, U128(2000));
+    }
+}
@@ -29,6 +29,8 @@ trait RefFinanceInterface {
     fn get_pool(&self, pool_id: PoolId);
 
     fn get_liquidity(&self, lpt_id: LptId);
+
+    fn withdraw_asset(&mut self, token_id: AccountId, amount: Option<U128>);
 }
 
 /// Single swap action.
@@ -41,6 +43,9 @@ pub struct Swap {
     pub output_token: AccountId,
     /// Required minimum amount of token_out.
     pub min_output_amount: U128,
+    /// Message the output is sent back with via `ft_transfer_call`, plain transfer is used if not specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_echo: Option<String>,
 }
 
 /// Single action. Allows to execute sequence of various actions initiated by an account.
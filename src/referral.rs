@@ -0,0 +1,160 @@
+use crate::*;
+
+/// Maximal length of the referral code
+const MAX_REFERRAL_CODE_LENGTH: usize = 32;
+
+#[near_bindgen]
+impl Contract {
+    /// Registers referral code of the caller, which could be used by other accounts to set it as referrer.
+    pub fn register_referral_code(&mut self, referral_code: String) {
+        require!(
+            !referral_code.is_empty() && referral_code.len() <= MAX_REFERRAL_CODE_LENGTH,
+            format!(
+                "Referral code length should be from 1 to {}",
+                MAX_REFERRAL_CODE_LENGTH
+            )
+        );
+        require!(
+            self.referral_codes.get(&referral_code).is_none(),
+            "Referral code is already registered"
+        );
+
+        self.referral_codes
+            .insert(&referral_code, &env::predecessor_account_id());
+    }
+
+    /// Sets referrer of the caller by referral code. Referrer could be set only once.
+    pub fn register_referrer(&mut self, referral_code: String) {
+        let account_id = env::predecessor_account_id();
+        require!(
+            self.referrers.get(&account_id).is_none(),
+            "Referrer is already registered"
+        );
+
+        self.apply_referral_code(&account_id, referral_code);
+    }
+
+    pub fn view_referral_share(&self) -> U128 {
+        U128(self.referral_share)
+    }
+
+    pub fn view_referrer(&self, account_id: AccountId) -> Option<AccountId> {
+        self.referrers.get(&account_id)
+    }
+
+    pub fn view_referral_code_owner(&self, referral_code: String) -> Option<AccountId> {
+        self.referral_codes.get(&referral_code)
+    }
+
+    pub fn view_referral_stats(&self, account_id: AccountId) -> ReferralStats {
+        self.referral_stats.get(&account_id).unwrap_or_default()
+    }
+}
+
+impl Contract {
+    pub fn set_referral_share(&mut self, share: U128) {
+        Self::assert_share_valid(share, "Referral share");
+        self.referral_share = share.0;
+    }
+
+    /// Sets the referrer by referral code if the account doesn't have one yet.
+    pub fn apply_referral_code(&mut self, account_id: &AccountId, referral_code: String) {
+        if self.referrers.get(account_id).is_some() {
+            return;
+        }
+
+        let referrer = self
+            .referral_codes
+            .get(&referral_code)
+            .unwrap_or_else(|| panic!("Referral code {} not found", referral_code));
+        require!(&referrer != account_id, "Account can't refer itself");
+
+        self.referrers.insert(account_id, &referrer);
+
+        let mut stats = self.referral_stats.get(&referrer).unwrap_or_default();
+        stats.referred_accounts += 1;
+        self.referral_stats.insert(&referrer, &stats);
+    }
+
+    /// Splits opening or closing fee between the referrer of the account & protocol.
+    pub fn collect_trading_fee(&mut self, account_id: &AccountId, token: &AccountId, fee: Balance) {
+        let referral_fee = match self.referrers.get(account_id) {
+            Some(referrer) => {
+                let referral_fee = Self::calculate_fee(fee, U128(self.referral_share));
+                if referral_fee > 0 {
+                    self.increase_balance(&referrer, token, referral_fee);
+
+                    let mut stats = self.referral_stats.get(&referrer).unwrap_or_default();
+                    let earned = stats
+                        .earnings
+                        .get(token)
+                        .map(|amount| amount.0)
+                        .unwrap_or(0);
+                    stats
+                        .earnings
+                        .insert(token.clone(), U128(earned + referral_fee));
+                    self.referral_stats.insert(&referrer, &stats);
+                }
+                referral_fee
+            }
+            None => 0,
+        };
+
+        self.add_protocol_profit(token, fee - referral_fee);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::test_env::{alice, bob};
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_referral_share_is_credited_to_referrer() {
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice())
+            .build());
+        contract.register_referral_code("alice".to_string());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(bob())
+            .build());
+        contract.register_referrer("alice".to_string());
+        assert_eq!(contract.view_referrer(bob()), Some(alice()));
+
+        contract.collect_trading_fee(&bob(), &token, 10_u128.pow(24));
+
+        assert_eq!(contract.balance_of(alice(), token.clone()), 10_u128.pow(23));
+        assert_eq!(
+            contract.protocol_profit.get(&token).unwrap(),
+            9 * 10_u128.pow(23)
+        );
+
+        let stats = contract.view_referral_stats(alice());
+        assert_eq!(stats.referred_accounts, 1);
+        assert_eq!(stats.earnings.get(&token), Some(&U128(10_u128.pow(23))));
+    }
+
+    #[test]
+    #[should_panic(expected = "Account can't refer itself")]
+    fn test_self_referral_is_forbidden() {
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(alice())
+            .build());
+        contract.register_referral_code("alice".to_string());
+        contract.register_referrer("alice".to_string());
+    }
+}
@@ -0,0 +1,301 @@
+use crate::events::Events;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::log;
+
+/// Delay of the parameter changes set on contract initialization, 1 day
+pub const DEFAULT_TIMELOCK_DELAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Timelock delay can't be set lower than 1 hour
+const MIN_TIMELOCK_DELAY: u64 = 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl Contract {
+    /// Schedules the parameter change, which could be applied by anyone after the timelock delay.
+    /// Returns id of the created proposal.
+    pub fn propose_change(&mut self, change: ParameterChange) -> U64 {
+        self.assert_can_propose(&change);
+
+        let proposed_at = env::block_timestamp();
+        let proposal_id = self.proposal_nonce;
+        self.proposal_nonce += 1;
+
+        let proposal = ParameterProposal {
+            change,
+            proposer: env::predecessor_account_id(),
+            proposed_at,
+            executable_at: proposed_at + self.timelock_delay,
+        };
+        self.proposals.insert(&proposal_id, &proposal);
+
+        log!(
+            "{}",
+            Events::ParameterChangeProposed(proposal_id, proposal.change, proposal.executable_at)
+        );
+
+        U64(proposal_id)
+    }
+
+    pub fn cancel_proposal(&mut self, proposal_id: U64) {
+        self.assert_role(Role::Guardian);
+        self.proposals
+            .remove(&proposal_id.0)
+            .unwrap_or_else(|| panic!("Proposal {} not found", proposal_id.0));
+
+        log!("{}", Events::ParameterChangeCancelled(proposal_id.0));
+    }
+
+    /// Applies the proposed parameter change once the timelock delay has passed.
+    /// Pair is added asynchronously after its verification against ref finance pool & market,
+    /// the proposal is kept if the verification fails.
+    pub fn apply_proposal(&mut self, proposal_id: U64) -> PromiseOrValue<bool> {
+        let proposal = self
+            .proposals
+            .get(&proposal_id.0)
+            .unwrap_or_else(|| panic!("Proposal {} not found", proposal_id.0));
+        require!(
+            env::block_timestamp() >= proposal.executable_at,
+            format!(
+                "Proposal can't be applied before {}",
+                proposal.executable_at
+            )
+        );
+
+        match proposal.change {
+            ParameterChange::AddPair { pair_data } => {
+                self.add_pair(pair_data, proposal_id.0).into()
+            }
+            change => {
+                self.proposals.remove(&proposal_id.0);
+                self.apply_change(change);
+
+                log!("{}", Events::ParameterChangeApplied(proposal_id.0));
+                PromiseOrValue::Value(true)
+            }
+        }
+    }
+
+    pub fn view_proposal(&self, proposal_id: U64) -> Option<ParameterProposal> {
+        self.proposals.get(&proposal_id.0)
+    }
+
+    pub fn view_proposals(&self) -> Vec<(U64, ParameterProposal)> {
+        self.proposals
+            .iter()
+            .map(|(proposal_id, proposal)| (U64(proposal_id), proposal))
+            .collect()
+    }
+
+    pub fn view_timelock_delay(&self) -> U64 {
+        U64(self.timelock_delay)
+    }
+}
+
+impl Contract {
+    /// Checks the caller role required for the change & validates the new value early.
+    fn assert_can_propose(&self, change: &ParameterChange) {
+        match change {
+            ParameterChange::LiquidationThreshold { .. }
+            | ParameterChange::VolatilityRate { .. } => self.assert_role(Role::RiskManager),
+            ParameterChange::SlippageTolerance { tolerance } => {
+                self.assert_role(Role::RiskManager);
+                Self::assert_slippage_tolerance_valid(*tolerance);
+            }
+            ParameterChange::FeeTiers { tiers } => {
+                self.assert_role(Role::Admin);
+                Self::assert_fee_tiers_valid(tiers);
+            }
+            ParameterChange::PoolFeeOwnerShare { share } => {
+                self.assert_role(Role::Admin);
+                Self::assert_share_valid(*share, "Pool fee owner share");
+            }
+            ParameterChange::ReferralShare { share } => {
+                self.assert_role(Role::Admin);
+                Self::assert_share_valid(*share, "Referral share");
+            }
+            ParameterChange::MinExecutorBounty { .. } | ParameterChange::MinTokenBounty { .. } => {
+                self.assert_role(Role::Admin)
+            }
+            ParameterChange::DefaultFees { fees } | ParameterChange::PairFees { fees, .. } => {
+                self.assert_role(Role::Admin);
+                Self::assert_fees_valid(fees);
+            }
+            ParameterChange::AddPair { pair_data } => {
+                self.assert_role(Role::Admin);
+                self.verify_pair(pair_data)
+                    .unwrap_or_else(|reason| env::panic_str(&reason));
+            }
+            ParameterChange::RemovePairFees { .. } | ParameterChange::RemovePair { .. } => {
+                self.assert_role(Role::Admin)
+            }
+            ParameterChange::OracleAccount { account_id }
+            | ParameterChange::RefFinanceAccount { account_id } => {
+                self.assert_owner();
+                Self::assert_external_account(account_id);
+            }
+            ParameterChange::TimelockDelay { delay } => {
+                self.assert_owner();
+                require!(
+                    delay.0 >= MIN_TIMELOCK_DELAY,
+                    format!("Timelock delay should be at least {}", MIN_TIMELOCK_DELAY)
+                );
+            }
+        }
+    }
+
+    fn apply_change(&mut self, change: ParameterChange) {
+        match change {
+            ParameterChange::LiquidationThreshold { threshold } => {
+                self.set_liquidation_threshold(threshold)
+            }
+            ParameterChange::VolatilityRate { rate } => self.set_volatility_rate(rate),
+            ParameterChange::DefaultFees { fees } => self.set_default_fees(fees),
+            ParameterChange::PairFees {
+                sell_token,
+                buy_token,
+                fees,
+            } => self.set_pair_fees(sell_token, buy_token, fees),
+            ParameterChange::RemovePairFees {
+                sell_token,
+                buy_token,
+            } => self.remove_pair_fees(sell_token, buy_token),
+            ParameterChange::AddPair { .. } => env::panic_str("Pair is added asynchronously"),
+            ParameterChange::RemovePair {
+                sell_token,
+                buy_token,
+            } => self.remove_pair(sell_token, buy_token),
+            ParameterChange::OracleAccount { account_id } => {
+                self.config.oracle_account_id = account_id
+            }
+            ParameterChange::RefFinanceAccount { account_id } => {
+                self.ref_finance_account = account_id
+            }
+            ParameterChange::TimelockDelay { delay } => self.timelock_delay = delay.0,
+            ParameterChange::SlippageTolerance { tolerance } => {
+                self.set_slippage_tolerance(tolerance)
+            }
+            ParameterChange::FeeTiers { tiers } => self.set_fee_tiers(tiers),
+            ParameterChange::PoolFeeOwnerShare { share } => self.set_pool_fee_owner_share(share),
+            ParameterChange::ReferralShare { share } => self.set_referral_share(share),
+            ParameterChange::MinExecutorBounty { bounty } => self.set_min_executor_bounty(bounty),
+            ParameterChange::MinTokenBounty { token, bounty } => {
+                self.set_min_token_bounty(token, bounty)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::test_env::alice;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    fn set_context(predecessor: AccountId, block_timestamp: u64) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(predecessor)
+            .block_timestamp(block_timestamp)
+            .build());
+    }
+
+    #[test]
+    fn test_change_is_applied_after_delay() {
+        let owner: AccountId = "owner_id.testnet".parse().unwrap();
+        set_context(owner.clone(), 0);
+        let mut contract =
+            Contract::new_with_config(owner, "oracle_account_id.testnet".parse().unwrap());
+
+        let proposal_id = contract.propose_change(ParameterChange::LiquidationThreshold {
+            threshold: U128(2 * 10_u128.pow(23)),
+        });
+        assert_eq!(
+            contract.view_proposal(proposal_id).unwrap().executable_at,
+            DEFAULT_TIMELOCK_DELAY
+        );
+
+        set_context(alice(), DEFAULT_TIMELOCK_DELAY);
+        contract.apply_proposal(proposal_id);
+
+        assert_eq!(contract.liquidation_threshold, 2 * 10_u128.pow(23));
+        assert!(contract.view_proposals().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Proposal can't be applied before")]
+    fn test_change_cant_be_applied_before_delay() {
+        let owner: AccountId = "owner_id.testnet".parse().unwrap();
+        set_context(owner.clone(), 0);
+        let mut contract =
+            Contract::new_with_config(owner, "oracle_account_id.testnet".parse().unwrap());
+
+        let proposal_id = contract.propose_change(ParameterChange::OracleAccount {
+            account_id: "oracle_v2.testnet".parse().unwrap(),
+        });
+
+        set_context(alice(), DEFAULT_TIMELOCK_DELAY - 1);
+        contract.apply_proposal(proposal_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Proposal 0 not found")]
+    fn test_guardian_cancels_proposal() {
+        let owner: AccountId = "owner_id.testnet".parse().unwrap();
+        set_context(owner.clone(), 0);
+        let mut contract =
+            Contract::new_with_config(owner, "oracle_account_id.testnet".parse().unwrap());
+        contract.grant_role(alice(), Role::Guardian);
+
+        let proposal_id = contract.propose_change(ParameterChange::VolatilityRate {
+            rate: U128(9 * 10_u128.pow(23)),
+        });
+
+        set_context(alice(), 0);
+        contract.cancel_proposal(proposal_id);
+
+        set_context(alice(), DEFAULT_TIMELOCK_DELAY);
+        contract.apply_proposal(proposal_id);
+    }
+
+    #[test]
+    fn test_risk_and_fee_parameters_are_timelocked() {
+        let owner: AccountId = "owner_id.testnet".parse().unwrap();
+        set_context(owner.clone(), 0);
+        let mut contract =
+            Contract::new_with_config(owner, "oracle_account_id.testnet".parse().unwrap());
+
+        let proposal_id = contract.propose_change(ParameterChange::SlippageTolerance {
+            tolerance: U128(10_u128.pow(22)),
+        });
+        let bounty_proposal_id = contract.propose_change(ParameterChange::MinTokenBounty {
+            token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            bounty: U128(100),
+        });
+        assert_eq!(
+            contract.view_slippage_tolerance(),
+            U128(5 * 10_u128.pow(22))
+        );
+
+        set_context(alice(), DEFAULT_TIMELOCK_DELAY);
+        contract.apply_proposal(proposal_id);
+        contract.apply_proposal(bounty_proposal_id);
+
+        assert_eq!(contract.view_slippage_tolerance(), U128(10_u128.pow(22)));
+        assert_eq!(
+            contract.view_min_token_bounty("usdt.qa.v1.nearlend.testnet".parse().unwrap()),
+            Some(U128(100))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Referral share should not exceed 100%")]
+    fn test_invalid_share_cant_be_proposed() {
+        let owner: AccountId = "owner_id.testnet".parse().unwrap();
+        set_context(owner.clone(), 0);
+        let mut contract =
+            Contract::new_with_config(owner, "oracle_account_id.testnet".parse().unwrap());
+
+        contract.propose_change(ParameterChange::ReferralShare {
+            share: U128(2 * 10_u128.pow(24)),
+        });
+    }
+}
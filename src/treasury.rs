@@ -0,0 +1,118 @@
+use crate::utils::ext_token;
+use crate::*;
+use near_sdk::env::current_account_id;
+use near_sdk::{ext_contract, is_promise_success, log, Gas, Promise, ONE_YOCTO};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_CLAIM_CALLBACK: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_self)]
+trait ContractCallbackInterface {
+    fn claim_protocol_profit_callback(&mut self, token: AccountId, amount: U128);
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_treasury_account(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.treasury_account = account_id;
+    }
+
+    /// Transfers accrued protocol profit in the given token to the treasury account.
+    /// Profit is restored if the transfer fails.
+    pub fn claim_protocol_profit(&mut self, token: AccountId, amount: U128) -> Promise {
+        self.assert_owner();
+        let profit = self.protocol_profit.get(&token).unwrap_or_default();
+        require!(
+            amount.0 > 0 && amount.0 <= profit,
+            "Amount should be positive & not greater than accrued protocol profit"
+        );
+
+        self.protocol_profit.insert(&token, &(profit - amount.0));
+
+        ext_token::ext(token.clone())
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .with_attached_deposit(ONE_YOCTO)
+            .ft_transfer(self.treasury_account.clone(), amount, None)
+            .then(
+                ext_self::ext(current_account_id())
+                    .with_static_gas(GAS_FOR_CLAIM_CALLBACK)
+                    .claim_protocol_profit_callback(token, amount),
+            )
+    }
+
+    #[private]
+    pub fn claim_protocol_profit_callback(&mut self, token: AccountId, amount: U128) {
+        if !is_promise_success() {
+            log!(
+                "Failed to transfer {} of {} protocol profit to treasury",
+                amount.0,
+                token
+            );
+            self.add_protocol_profit(&token, amount.0);
+        }
+    }
+
+    pub fn view_treasury_account(&self) -> AccountId {
+        self.treasury_account.clone()
+    }
+
+    pub fn view_protocol_profit(&self, token: AccountId) -> U128 {
+        U128(self.protocol_profit.get(&token).unwrap_or_default())
+    }
+
+    /// Returns all tokens with accrued protocol profit.
+    pub fn view_protocol_profits(&self) -> Vec<(AccountId, U128)> {
+        self.protocol_profit
+            .iter()
+            .filter(|(_, amount)| *amount > 0)
+            .map(|(token, amount)| (token, U128(amount)))
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Records collected fee as protocol profit in the given token.
+    pub fn add_protocol_profit(&mut self, token: &AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+
+        let token_profit = self.protocol_profit.get(token).unwrap_or_default();
+        self.protocol_profit.insert(token, &(token_profit + amount));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    #[test]
+    fn test_failed_claim_restores_protocol_profit() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
+            .build());
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+        let token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        contract.add_protocol_profit(&token, 1000);
+
+        let _ = contract.claim_protocol_profit(token.clone(), U128(400));
+        assert_eq!(contract.view_protocol_profit(token.clone()), U128(600));
+
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.claim_protocol_profit_callback(token.clone(), U128(400));
+
+        assert_eq!(contract.view_protocol_profits(), vec![(token, U128(1000))]);
+    }
+}
@@ -14,6 +14,8 @@ pub trait NEP141Token {
     );
 
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: WBalance, memo: Option<String>);
+
+    fn ft_balance_of(&self, account_id: AccountId) -> WBalance;
 }
 
 impl Contract {
@@ -19,6 +19,8 @@ impl Contract {
             })
             .clone();
 
+        let fees = self.get_account_fees(&account_id, &order.sell_token, &order.buy_token);
+
         OrderView {
             order_id,
             status: order.status,
@@ -28,8 +30,10 @@ impl Contract {
             buy_token: order.buy_token,
             leverage: WBigDecimal::from(order.leverage),
             buy_token_price: WBalance::from(order.buy_token_price.value),
-            fee: U128(3 * 10u128.pow(23)), // hardcore of 0.3 %
+            fee: U128(fees.opening_fee.0 + fees.closing_fee.0),
             lpt_id: order.lpt_id,
+            accrued_fee_x: U128(order.accrued_fee_x),
+            accrued_fee_y: U128(order.accrued_fee_y),
         }
     }
 
@@ -50,40 +54,37 @@ impl Contract {
             })
             .clone();
 
-        let buy_amount =
-            order.leverage * BigDecimal::from(order.amount) / order.buy_token_price.value;
+        let collateral = BigDecimal::from(U128(order.amount));
+        let (sell_amount, buy_amount) = self.position_amounts(&order);
 
-        let borrow_amount = BigDecimal::from(U128(order.amount))
-            * (order.leverage - BigDecimal::one())
-            * BigDecimal::from(10_u128.pow(24));
+        // position value in sell token based on the actually filled amounts
+        let buy_amount_value = BigDecimal::from(U128(buy_amount))
+            * self.calculate_xrate(order.buy_token.clone(), order.sell_token.clone());
+        let position_value = BigDecimal::from(U128(sell_amount)) + buy_amount_value;
 
-        let borrow_fee = borrow_amount * BigDecimal::from(data.borrow_rate_ratio);
-        // fee by blocks count
-        //* BigDecimal::from(block_height() - order.block);
+        // borrowed amount alongside with the borrow fee accrued for the blocks the order is held
+        let debt = BigDecimal::from(U128(self.calculate_debt(&order, &data)));
 
-        //swap_fee 0.0003
-        let expect_amount = buy_amount * order.sell_token_price.value
-            - borrow_amount
-            - borrow_fee
-            - borrow_amount * BigDecimal::from(0.0003);
+        let fees = self.get_account_fees(&account_id, &order.sell_token, &order.buy_token);
 
-        let pnlv: PnLView = if expect_amount.round_u128() > order.amount {
-            let lenpnl = (expect_amount
-                - BigDecimal::from(order.amount)
-                - (BigDecimal::from(order.amount)
-                    * BigDecimal::from(self.protocol_fee / 10_u128.pow(24))))
-            .round_u128();
+        let closing_fee = position_value * BigDecimal::from(fees.closing_fee);
+        let expenses = debt + closing_fee;
+        let expect_amount = if position_value > expenses {
+            position_value - expenses
+        } else {
+            BigDecimal::zero()
+        };
 
+        let pnlv: PnLView = if expect_amount > collateral {
+            let profit = expect_amount - collateral;
             PnLView {
                 is_profit: true,
-                amount: U128(lenpnl),
+                amount: U128::from(profit - profit * BigDecimal::from(fees.profit_share_fee)),
             }
         } else {
-            let lenpnl = (BigDecimal::from(order.amount) - expect_amount).round_u128();
-
             PnLView {
                 is_profit: false,
-                amount: U128(lenpnl),
+                amount: U128::from(collateral - expect_amount),
             }
         };
 
@@ -97,6 +98,7 @@ impl Contract {
         buy_token: AccountId,
     ) -> Vec<OrderView> {
         let orders = self.orders.get(&account_id).unwrap_or_default();
+        let fees = self.get_account_fees(&account_id, &sell_token, &buy_token);
         let result = orders
             .iter()
             .filter_map(|(id, order)| {
@@ -110,8 +112,10 @@ impl Contract {
                         buy_token: order.buy_token.clone(),
                         leverage: WBigDecimal::from(order.leverage),
                         buy_token_price: WRatio::from(order.buy_token_price.value),
-                        fee: U128(self.protocol_fee),
+                        fee: U128(fees.opening_fee.0 + fees.closing_fee.0),
                         lpt_id: order.lpt_id.clone(),
+                        accrued_fee_x: U128(order.accrued_fee_x),
+                        accrued_fee_y: U128(order.accrued_fee_y),
                     }),
                     false => None,
                 }
@@ -123,7 +127,7 @@ impl Contract {
     pub fn view_pair(&self, sell_token: &AccountId, buy_token: &AccountId) -> TradePair {
         self.supported_markets
             .get(&(sell_token.clone(), buy_token.clone()))
-            .unwrap()
+            .unwrap_or_else(|| panic!("Pair {}/{} is not supported", sell_token, buy_token))
     }
 
     pub fn view_supported_pairs(&self) -> Vec<TradePair> {
@@ -165,31 +169,110 @@ impl Contract {
             panic!("Order with id: {} not found", order_id.0);
         });
 
-        let buy_token =
-            BigDecimal::from(U128(order.amount)) * order.leverage * order.sell_token_price.value
-                / order.buy_token_price.value;
-
-        let sell_token = BigDecimal::from(U128(order.amount)) * order.leverage;
-
-        let open_price = order.buy_token_price.clone();
-
-        let close_price = self.get_price(order.buy_token.clone());
+        let close_price = self.calculate_xrate(order.buy_token.clone(), order.sell_token.clone());
 
         let calc_pnl = self.calculate_pnl(account_id, order_id, market_data);
 
         CancelOrderView {
-            buy_token_amount: WRatio::from(buy_token),
-            sell_token_amount: WRatio::from(sell_token),
-            open_price: WRatio::from(open_price.value),
+            buy_token_amount: U128(order.filled_buy_amount),
+            sell_token_amount: U128(order.filled_sell_amount),
+            open_price: WRatio::from(order.execution_price),
             close_price: WRatio::from(close_price),
             pnl: calc_pnl,
         }
     }
 
+    /// Returns open orders, starting from the `from` one, which are below the liquidation threshold
+    /// at current prices alongside with the expected liquidator reward.
+    /// Orders of tokens without a registered market are skipped.
+    pub fn view_liquidatable_orders(&self, from: u64, limit: u64) -> Vec<LiquidatableOrderView> {
+        self.orders
+            .iter()
+            .flat_map(|(account_id, orders)| {
+                let mut orders = orders.into_iter().collect::<Vec<(u64, Order)>>();
+                orders.sort_by_key(|(order_id, _)| *order_id);
+                orders
+                    .into_iter()
+                    .map(move |(order_id, order)| (account_id.clone(), order_id, order))
+            })
+            .filter(|(_, _, order)| self.is_order_open(order))
+            .skip(from as usize)
+            .take(limit as usize)
+            .filter_map(|(account_id, order_id, order)| {
+                let market = self.tokens_markets.get(&order.sell_token)?;
+                let market_data = self.view_market_data(market);
+                let (is_liquidation_possible, liquidator_reward) =
+                    self.calculate_liquidation(&order, &market_data);
+
+                match is_liquidation_possible {
+                    true => Some(LiquidatableOrderView {
+                        account_id,
+                        order_id: U128(order_id as u128),
+                        status: order.status,
+                        sell_token: order.sell_token,
+                        buy_token: order.buy_token,
+                        amount: U128(order.amount),
+                        liquidator_reward: U128(liquidator_reward),
+                    }),
+                    false => None,
+                }
+            })
+            .collect::<Vec<LiquidatableOrderView>>()
+    }
+
+    /// Returns pending orders of the given pair whose price range has been fully crossed
+    /// at the pool `current_point`, so they are ready to be executed.
+    pub fn view_executable_orders(
+        &self,
+        sell_token: AccountId,
+        buy_token: AccountId,
+        current_point: i32,
+    ) -> Vec<ExecutableOrderView> {
+        self.orders
+            .iter()
+            .flat_map(|(account_id, orders)| {
+                orders
+                    .into_iter()
+                    .map(move |(order_id, order)| (account_id.clone(), order_id, order))
+            })
+            .filter_map(|(account_id, order_id, order)| {
+                match order.status == OrderStatus::Pending
+                    && order.sell_token == sell_token
+                    && order.buy_token == buy_token
+                    && self.is_order_range_crossed(&order, current_point)
+                {
+                    true => Some(ExecutableOrderView {
+                        account_id,
+                        order_id: U128(order_id as u128),
+                        amount: U128(order.amount),
+                        lpt_id: order.lpt_id,
+                        left_point: order.left_point,
+                        right_point: order.right_point,
+                        executor_reward: U128(order.executor_bounty),
+                        executor_reward_token: order.bounty_token,
+                    }),
+                    false => None,
+                }
+            })
+            .collect::<Vec<ExecutableOrderView>>()
+    }
+
     pub fn view_liquidation_threshold(&self) -> U128 {
         U128(self.liquidation_threshold)
     }
 
+    pub fn view_min_executor_bounty(&self) -> U128 {
+        U128(self.min_executor_bounty)
+    }
+
+    pub fn view_min_token_bounty(&self, token: AccountId) -> Option<U128> {
+        self.min_token_bounties.get(&token).map(U128)
+    }
+
+    pub fn view_slippage_tolerance(&self) -> U128 {
+        U128(self.slippage_tolerance)
+    }
+
     pub fn calculate_liquidation_price(
         &self,
         sell_token_amount: U128,
@@ -220,13 +303,13 @@ mod tests {
 
     use near_sdk::test_utils::test_env::alice;
     use near_sdk::test_utils::VMContextBuilder;
-    use near_sdk::{serde_json, testing_env, FunctionError, VMContext};
+    use near_sdk::{testing_env, VMContext};
 
     fn get_context(is_view: bool) -> VMContext {
         VMContextBuilder::new()
             .current_account_id("margin.nearland.testnet".parse().unwrap())
             .signer_account_id(alice())
-            .predecessor_account_id("usdt_market.qa.nearland.testnet".parse().unwrap())
+            .predecessor_account_id("owner_id.testnet".parse().unwrap())
             .block_index(721)
             .block_timestamp(1)
             .is_view(is_view)
@@ -249,7 +332,7 @@ mod tests {
             buy_token: "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
             pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
         };
-        contract.add_pair(pair_data.clone());
+        contract.insert_pair(pair_data.clone());
 
         let pair_data2 = TradePair {
             sell_ticker_id: "wnear".to_string(),
@@ -260,7 +343,7 @@ mod tests {
             pool_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000".to_string(),
         };
 
-        contract.add_pair(pair_data2.clone());
+        contract.insert_pair(pair_data2.clone());
 
         let result = vec![pair_data, pair_data2];
         let pairs = contract.view_supported_pairs();
@@ -287,10 +370,10 @@ mod tests {
             "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
             Price {
                 ticker_id: "WNEAR".to_string(),
-                value: BigDecimal::from(4.22),
+                value: BigDecimal::from(9.0),
             },
         );
-        let order1 = "{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1500000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"2.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"3.3\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.59\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\"}".to_string();
+        let order1 = "{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1500000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"2.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"3.3\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.59\"},\"block\":719,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":600000000000000000000000000,\"filled_sell_amount\":3000000000000000000000000000,\"execution_block\":1,\"execution_price\":\"5.0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
         contract.add_order(alice(), order1.clone());
         let market_data = MarketData {
             total_supplies: U128(10_u128.pow(24)),
@@ -300,9 +383,106 @@ mod tests {
             interest_rate_ratio: U128(10_u128.pow(24)),
             borrow_rate_ratio: U128(5 * 10_u128.pow(22)),
         };
+        // borrow fee of 5% is accrued for 2 blocks the order is held
         let pnl = contract.calculate_pnl(alice(), U128(1), market_data);
         assert!(!pnl.is_profit);
-        assert_eq!(pnl.amount, U128(918587254901960784313725490));
+        assert_eq!(pnl.amount, U128(450810000000000000000000000));
+    }
+
+    #[test]
+    fn view_liquidatable_orders_test() {
+        let context = get_context(false);
+        testing_env!(context);
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+
+        contract.add_token_market(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            "usdt_market.qa.v1.nearlend.testnet".parse().unwrap(),
+        );
+        contract.update_or_insert_price(
+            "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "USDT".to_string(),
+                value: BigDecimal::from(1.0),
+            },
+        );
+        contract.update_or_insert_price(
+            "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+            Price {
+                ticker_id: "WNEAR".to_string(),
+                value: BigDecimal::from(1.0),
+            },
+        );
+
+        let order1 = "{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"2.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":600000000000000000000000000,\"filled_sell_amount\":3000000000000000000000000000,\"execution_block\":1,\"execution_price\":\"5.0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
+        let order2 = "{\"status\":\"Executed\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"2.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"1.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#133\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":2000000000000000000000000000,\"filled_sell_amount\":3000000000000000000000000000,\"execution_block\":1,\"execution_price\":\"5.0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
+        // sell token has no market registered, so the order is skipped instead of panicking
+        let order3 = order1.replace("usdt.qa.v1.nearlend.testnet", "dai.qa.v1.nearlend.testnet");
+        // pending order holds sell token only, so it isn't affected by the buy token price
+        let order4 = order1
+            .replace("\"Executed\"", "\"Pending\"")
+            .replace("600000000000000000000000000", "0")
+            .replace("3000000000000000000000000000", "0");
+        contract.add_order(alice(), order1);
+        contract.add_order(alice(), order2);
+        contract.add_order(alice(), order3);
+        contract.add_order(alice(), order4);
+
+        let result = contract.view_liquidatable_orders(0, 10);
+        assert_eq!(
+            result,
+            vec![LiquidatableOrderView {
+                account_id: alice(),
+                order_id: U128(1),
+                status: OrderStatus::Executed,
+                sell_token: "usdt.qa.v1.nearlend.testnet".parse().unwrap(),
+                buy_token: "wnear.qa.v1.nearlend.testnet".parse().unwrap(),
+                amount: U128(10_u128.pow(27)),
+                liquidator_reward: U128(10_u128.pow(26)),
+            }]
+        );
+
+        assert!(contract.view_liquidatable_orders(1, 10).is_empty());
+        assert!(contract.view_liquidatable_orders(2, 10).is_empty());
+    }
+
+    #[test]
+    fn view_executable_orders_test() {
+        let context = get_context(false);
+        testing_env!(context);
+        let mut contract = Contract::new_with_config(
+            "owner_id.testnet".parse().unwrap(),
+            "oracle_account_id.testnet".parse().unwrap(),
+        );
+
+        let order1 = "{\"status\":\"Pending\",\"order_type\":\"Buy\",\"amount\":1000000000000000000000000000,\"sell_token\":\"usdt.qa.v1.nearlend.testnet\",\"buy_token\":\"wnear.qa.v1.nearlend.testnet\",\"leverage\":\"1.0\",\"sell_token_price\":{\"ticker_id\":\"USDT\",\"value\":\"1.0\"},\"buy_token_price\":{\"ticker_id\":\"WNEAR\",\"value\":\"4.0\"},\"block\":1,\"lpt_id\":\"usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132\",\"left_point\":0,\"right_point\":40,\"remaining_sell_amount\":0,\"filled_buy_amount\":600000000000000000000000000,\"filled_sell_amount\":3000000000000000000000000000,\"execution_block\":1,\"execution_price\":\"5.0\",\"accrued_fee_x\":0,\"accrued_fee_y\":0,\"executor_bounty\":0,\"bounty_token\":null}".to_string();
+        contract.add_order(alice(), order1);
+
+        let sell_token: AccountId = "usdt.qa.v1.nearlend.testnet".parse().unwrap();
+        let buy_token: AccountId = "wnear.qa.v1.nearlend.testnet".parse().unwrap();
+
+        assert!(contract
+            .view_executable_orders(sell_token.clone(), buy_token.clone(), 39)
+            .is_empty());
+
+        let result = contract.view_executable_orders(sell_token, buy_token, 40);
+        assert_eq!(
+            result,
+            vec![ExecutableOrderView {
+                account_id: alice(),
+                order_id: U128(1),
+                amount: U128(10_u128.pow(27)),
+                lpt_id: "usdt.qa.v1.nearlend.testnet|wnear.qa.v1.nearlend.testnet|2000#132"
+                    .to_string(),
+                left_point: 0,
+                right_point: 40,
+                executor_reward: U128(0),
+                executor_reward_token: None,
+            }]
+        );
     }
 
     #[test]
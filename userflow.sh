@@ -10,7 +10,7 @@ near call limit_orders.v1.nearlend.testnet create_order '{
     "sell_token": "usdt.qa.v1.nearlend.testnet",
     "buy_token": "wnear.qa.v1.nearlend.testnet",
     "leverage": "1000000000000000000000000" 
-}' --accountId nearlend.testnet --gas 300000000000000
+}' --accountId nearlend.testnet --amount 0.01 --gas 300000000000000
 
 near call limit_orders.v1.nearlend.testnet create_order '{
     "order_type": "Buy",
@@ -18,7 +18,7 @@ near call limit_orders.v1.nearlend.testnet create_order '{
     "sell_token": "usdt.qa.v1.nearlend.testnet",
     "buy_token": "wnear.qa.v1.nearlend.testnet",
     "leverage": "1000000000000000000000000" 
-}' --accountId nearlend.testnet --gas 300000000000000
+}' --accountId nearlend.testnet --amount 0.01 --gas 300000000000000
 
 
 near view limit_orders.v1.nearlend.testnet view_orders '{
@@ -52,12 +52,9 @@ do
 done
 wait
 
-# swap_fee 0.002 = 0.2%
-# price_impact 0.05 = 5%
 near call limit_orders.v1.nearlend.testnet cancel_order '{
     "order_id": "4",
-    "swap_fee": "2000000000000000000000",
-    "price_impact": "50000000000000000000000"
+    "min_out": "0"
 }' --accountId nearlend.testnet --gas 300000000000000
 
 
@@ -93,7 +90,7 @@ near view limit_orders.v1.nearlend.testnet balance_of '{"account_id": "nearlend.
 #         "point_delta": 40,
 #         "current_point": -11333
 #     }
-# }' --accountId nearlend.testnet --gas 300000000000000
+# }' --accountId nearlend.testnet --amount 0.01 --gas 300000000000000
 
 # amount = 1000.0
 # leverage = 1.0
@@ -107,7 +104,7 @@ near view limit_orders.v1.nearlend.testnet balance_of '{"account_id": "nearlend.
 #         "point_delta": 40,
 #         "current_point": -11333
 #     } 
-# }' --accountId nearlend.testnet --gas 300000000000000
+# }' --accountId nearlend.testnet --amount 0.01 --gas 300000000000000
 
 near call limit_orders.v1.nearlend.testnet execute_order '{
     "order_id": "2"